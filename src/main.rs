@@ -1,4 +1,10 @@
-use std::{collections::HashSet, io, process::exit, time::Duration};
+use std::{
+    collections::HashSet,
+    io,
+    process::exit,
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc;
 use tokio_i3ipc::{
     event as I3Event,
     event::{Event, Subscribe},
@@ -8,7 +14,10 @@ use tokio_i3ipc::{
 use tokio_stream::StreamExt;
 
 mod types;
-use types::config::{Config, TomlConfig};
+use types::{
+    config::{Config, TomlConfig},
+    traits::BackgroundSender,
+};
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> io::Result<()> {
@@ -47,6 +56,22 @@ async fn try_i3_connection(
     .await
 }
 
+/// Wait until `deadline`, or forever if there isn't one
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// What woke up the listener loop
+enum Wakeup {
+    Event(Event),
+    Timeout,
+    /// Result of a background task, for the handler at the given index
+    Background(usize, String),
+}
+
 /// Main listener loop
 async fn listener(mut config: Config) -> io::Result<()> {
     // Set up event handlers
@@ -56,8 +81,12 @@ async fn listener(mut config: Config) -> io::Result<()> {
         h.add_subscriptions(&mut subs);
     }
     let subs: Vec<Subscribe> = subs.iter().map(|&s| s.into()).collect();
+    let (bg_tx, mut bg_rx) = mpsc::unbounded_channel();
+    for (i, h) in handlers.iter_mut().enumerate() {
+        h.set_background(BackgroundSender::new(i, bg_tx.clone()));
+    }
 
-    loop {
+    'connect: loop {
         let mut i3 =
             try_i3_connection(config.connection_timeout, config.reconnect_interval).await?;
         let _resp = i3.subscribe(&subs).await?;
@@ -67,19 +96,50 @@ async fn listener(mut config: Config) -> io::Result<()> {
         let mut i3_tx = I3::connect().await?;
         let mut i3_rx = I3::connect().await?;
 
+        for handler in handlers.iter_mut() {
+            if let Some(msg) = handler.on_connect(&mut i3_rx).await {
+                if let Err(err) = i3_tx.send_msg_body(Msg::RunCommand, msg).await {
+                    eprintln!(
+                        "Error sending message to i3: {}\nAttempting to reconnect...",
+                        err
+                    );
+                    continue 'connect;
+                }
+            }
+        }
+
         let mut listener = i3.listen();
         let mut restart = false;
-        'listen: while let Some(event) = listener.next().await {
-            let event = event?;
-            if let Event::Shutdown(sd) = &event {
+        'listen: loop {
+            // Wait for the next event, handler timeout or background result
+            let deadline = handlers.iter().filter_map(|h| h.next_timeout()).min();
+            let wakeup = tokio::select! {
+                event = listener.next() => match event {
+                    Some(event) => Wakeup::Event(event?),
+                    None => break,
+                },
+                _ = sleep_until(deadline) => Wakeup::Timeout,
+                Some((idx, msg)) = bg_rx.recv() => Wakeup::Background(idx, msg),
+            };
+            if let Wakeup::Event(Event::Shutdown(sd)) = &wakeup {
                 if sd.change == I3Event::ShutdownChange::Restart {
                     restart = true;
                     eprintln!("i3 restart detected. Attempting to reconnect...");
                     break;
                 }
             }
-            for handler in handlers.iter_mut() {
-                if let Some(msg) = handler.handle_event(&event, &mut i3_rx).await {
+            for (i, handler) in handlers.iter_mut().enumerate() {
+                let msg = match &wakeup {
+                    Wakeup::Event(event) => handler.handle_event(event, &mut i3_rx).await,
+                    Wakeup::Timeout if matches!(handler.next_timeout(), Some(t) if t <= Instant::now()) => {
+                        handler.on_timeout(&mut i3_rx).await
+                    }
+                    Wakeup::Background(idx, msg) if *idx == i => {
+                        handler.on_background(msg, &mut i3_rx).await
+                    }
+                    _ => None,
+                };
+                if let Some(msg) = msg {
                     if let Err(err) = i3_tx.send_msg_body(Msg::RunCommand, msg).await {
                         restart = true;
                         eprintln!(
@@ -0,0 +1,87 @@
+use super::{
+    node_utils::{get_focused_node, ws_in_set, ws_matches, WorkspaceSet},
+    traits::OnEvent,
+};
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::collections::HashSet;
+use tokio_i3ipc::{
+    event::{Event, Subscribe, WindowChange},
+    reply::{Floating, FullscreenMode, NodeLayout},
+    I3,
+};
+
+/// Automatically alternate the split direction of focused windows, so that new windows are
+/// opened in a spiral/dwindle pattern.
+#[derive(Default)]
+pub struct Autotiling {
+    pub workspaces: Option<HashSet<String>>,
+    pub rule_workspaces: WorkspaceSet,
+    pub depth_limit: Option<usize>,
+}
+
+/// Config input for `Autotiling`
+#[derive(Deserialize)]
+pub struct AutotilingConfig {
+    pub workspaces: Option<Vec<String>>,
+    pub depth_limit: Option<usize>,
+}
+
+impl From<AutotilingConfig> for Autotiling {
+    fn from(config: AutotilingConfig) -> Self {
+        Self {
+            workspaces: config.workspaces.map(|w| w.into_iter().collect()),
+            rule_workspaces: WorkspaceSet::default(),
+            depth_limit: config.depth_limit.filter(|&d| d > 0),
+        }
+    }
+}
+
+impl Autotiling {
+    /// Work out which split command (if any) should be applied to the focused container
+    async fn get_split(&self, i3: &mut I3) -> Option<&'static str> {
+        let tree = i3.get_tree().await.ok()?;
+        let node = get_focused_node((&tree).into())?;
+        let parent = node.parent?;
+        let workspace = node.workspace?;
+        if matches!(&self.workspaces, Some(names)
+                if !ws_matches(names, workspace) && !ws_in_set(&self.rule_workspaces, workspace))
+            || matches!(self.depth_limit, Some(limit) if node.depth > limit)
+            || matches!(
+                node.focused.floating,
+                Some(Floating::AutoOn) | Some(Floating::UserOn)
+            )
+            || node.focused.fullscreen_mode != FullscreenMode::None
+            || matches!(parent.layout, NodeLayout::Tabbed | NodeLayout::Stacked)
+        {
+            return None;
+        }
+        let rect = &node.focused.rect;
+        let (layout, cmd) = if rect.height > rect.width {
+            (NodeLayout::SplitV, "split v")
+        } else {
+            (NodeLayout::SplitH, "split h")
+        };
+        if parent.layout != layout {
+            Some(cmd)
+        } else {
+            None
+        }
+    }
+}
+
+#[async_trait]
+impl OnEvent for Autotiling {
+    fn add_subscriptions(&self, subs: &mut HashSet<u32>) {
+        subs.insert(Subscribe::Window.into());
+    }
+
+    async fn handle_event(&mut self, e: &Event, i3: &mut I3) -> Option<String> {
+        match e {
+            Event::Window(w) if matches!(w.change, WindowChange::New | WindowChange::Focus) => {
+                self.get_split(i3).await.map(|cmd| cmd.into())
+            }
+            _ => None,
+        }
+    }
+}
@@ -1,5 +1,11 @@
 use super::{
+    autotiling::{Autotiling, AutotilingConfig},
+    layout_rules::{LayoutRules, LayoutRulesConfig},
+    layout_snapshot::{LayoutSnapshot, LayoutSnapshotConfig},
     layout_tracker::{LayoutTracker, LayoutTrackerConfig},
+    master_stack::{MasterStack, MasterStackConfig},
+    notifier::{Notifier, NotifierConfig},
+    output_history::{OutputHistory, OutputHistoryConfig},
     output_tracker::{OutputTracker, OutputTrackerConfig},
     parsable_duration::ParsableDuration,
     pipe_sender::PipeSender,
@@ -17,7 +23,7 @@ use toml;
 pub struct I3Timeout(ParsableDuration);
 impl From<Duration> for I3Timeout {
     fn from(d: Duration) -> Self {
-        Self { 0: d.into() }
+        Self(d.into())
     }
 }
 impl Default for I3Timeout {
@@ -30,7 +36,7 @@ impl Default for I3Timeout {
 pub struct I3Interval(ParsableDuration);
 impl From<Duration> for I3Interval {
     fn from(d: Duration) -> Self {
-        Self { 0: d.into() }
+        Self(d.into())
     }
 }
 impl Default for I3Interval {
@@ -46,8 +52,16 @@ pub struct TomlConfig {
     #[serde(default)]
     pub reconnect_interval: I3Interval, // millis
     pub ws_history: Option<WSHistoryConfig>,
+    pub output_history: Option<OutputHistoryConfig>,
     pub layout_tracker: Option<LayoutTrackerConfig>,
     pub output_tracker: Option<OutputTrackerConfig>,
+    pub autotiling: Option<AutotilingConfig>,
+    pub master_stack: Option<MasterStackConfig>,
+    pub layout_snapshot: Option<LayoutSnapshotConfig>,
+    pub layout_rules: Option<LayoutRulesConfig>,
+    pub notifications: Option<NotifierConfig>,
+    #[serde(skip)]
+    pub restore_session: bool,
     pub pipes: Option<HashMap<String, (SenderType, String)>>,
 }
 
@@ -55,67 +69,101 @@ pub struct Config {
     pub connection_timeout: Duration, // secs
     pub reconnect_interval: Duration, // millis
     pub ws_history: Option<WSHistory>,
+    pub output_history: Option<OutputHistory>,
     pub layout_tracker: Option<LayoutTracker>,
     pub output_tracker: Option<OutputTracker>,
-    pub pipes: Option<HashMap<String, Arc<dyn MsgSender + Send + Sync>>>,
+    pub autotiling: Option<Autotiling>,
+    pub master_stack: Option<MasterStack>,
+    pub layout_snapshot: Option<LayoutSnapshot>,
+    pub layout_rules: Option<LayoutRules>,
 }
 impl From<TomlConfig> for Config {
     fn from(config: TomlConfig) -> Self {
         let pipes: Option<HashMap<String, Arc<dyn MsgSender + Send + Sync>>> =
-            config.pipes.and_then(|h| {
-                Some(
-                    h.into_iter()
-                        .map(|p| {
-                            (
-                                p.0,
-                                match p.1 .0 {
-                                    SenderType::SHELL => Arc::new(ShellCaller::new(p.1 .1))
-                                        as Arc<dyn MsgSender + Send + Sync>,
-                                    SenderType::PIPE => Arc::new(PipeSender::new(p.1 .1))
-                                        as Arc<dyn MsgSender + Send + Sync>,
-                                },
-                            )
-                        })
-                        .collect(),
-                )
+            config.pipes.map(|h| {
+                h.into_iter()
+                    .map(|p| {
+                        (
+                            p.0,
+                            match p.1 .0 {
+                                SenderType::SHELL => Arc::new(ShellCaller::new(p.1 .1))
+                                    as Arc<dyn MsgSender + Send + Sync>,
+                                SenderType::PIPE => Arc::new(PipeSender::new(p.1 .1))
+                                    as Arc<dyn MsgSender + Send + Sync>,
+                            },
+                        )
+                    })
+                    .collect()
             });
+        let notifier: Arc<Notifier> = Arc::new(
+            config
+                .notifications
+                .map(|c| (c, pipes.as_ref()).into())
+                .unwrap_or_default(),
+        );
+        let autotiling: Option<Autotiling> = config.autotiling.map(|c| c.into());
+        let master_stack: Option<MasterStack> = config.master_stack.map(|c| c.into());
+        let layout_rules = config.layout_rules.map(|c| {
+            (
+                c,
+                autotiling.as_ref().map(|a| a.rule_workspaces.clone()),
+                master_stack.as_ref().map(|m| m.rule_workspaces.clone()),
+            )
+                .into()
+        });
         Self {
             connection_timeout: config.connection_timeout.0.into(),
             reconnect_interval: config.reconnect_interval.0.into(),
-            ws_history: config.ws_history.and_then(|c| Some(c.into())),
-            layout_tracker: config.layout_tracker.and_then(|c| {
-                Some(
-                    (
-                        c,
-                        pipes.as_ref().unwrap_or_else(|| {
-                            eprintln!("ERROR: Layout tracker requires a pipe");
-                            exit(7);
-                        }),
-                    )
-                        .into(),
+            ws_history: config
+                .ws_history
+                .map(|c| (c, pipes.as_ref(), notifier.clone()).into()),
+            output_history: config.output_history.map(|c| c.into()),
+            layout_tracker: config.layout_tracker.map(|c| {
+                (
+                    c,
+                    pipes.as_ref().unwrap_or_else(|| {
+                        eprintln!("ERROR: Layout tracker requires a pipe");
+                        exit(7);
+                    }),
                 )
+                    .into()
             }),
-            output_tracker: config.output_tracker.and_then(|c| {
-                Some(
-                    (
-                        c,
-                        pipes.as_ref().unwrap_or_else(|| {
-                            eprintln!("ERROR: Layout tracker requires a pipe");
-                            exit(7);
-                        }),
-                    )
-                        .into(),
+            output_tracker: config.output_tracker.map(|c| {
+                (
+                    c,
+                    pipes.as_ref().unwrap_or_else(|| {
+                        eprintln!("ERROR: Layout tracker requires a pipe");
+                        exit(7);
+                    }),
                 )
+                    .into()
             }),
-            pipes,
+            autotiling,
+            master_stack,
+            layout_snapshot: match config.layout_snapshot {
+                Some(c) => Some((c, config.restore_session).into()),
+                None if config.restore_session => {
+                    eprintln!("ERROR: --restore-session requires a [layout_snapshot] block");
+                    exit(7);
+                }
+                None => None,
+            },
+            layout_rules,
         }
     }
 }
 
-fn parse_cli() -> Option<PathBuf> {
+/// Options set on the command line
+#[derive(Default)]
+struct CliArgs {
+    config: Option<PathBuf>,
+    restore_session: bool,
+}
+
+fn parse_cli() -> CliArgs {
     let mut args = std::env::args_os();
     let appname = args.next();
-    let mut out: Option<PathBuf> = None;
+    let mut out = CliArgs::default();
     while let Some(arg) = args.next() {
         if arg == "-c" || arg == "--config" {
             let mut buf = PathBuf::new();
@@ -127,12 +175,14 @@ fn parse_cli() -> Option<PathBuf> {
                 eprintln!("ERROR: file does not exist\n{}", buf.to_str().unwrap_or(""));
                 exit(3);
             }
-            out = Some(buf);
+            out.config = Some(buf);
+        } else if arg == "--restore-session" {
+            out.restore_session = true;
         } else if arg == "-h" || arg == "--help" {
             println!(
-                "USAGE: {} [-c/--config CONFIG_FILE] [-h/--help]",
+                "USAGE: {} [-c/--config CONFIG_FILE] [--restore-session] [-h/--help]",
                 appname
-                    .and_then(|n| n.to_str().and_then(|s| Some(s.to_string())))
+                    .and_then(|n| n.to_str().map(|s| s.to_string()))
                     .unwrap_or("i3_companion".into())
             );
             exit(0);
@@ -145,7 +195,7 @@ impl TomlConfig {
     pub fn new() -> std::io::Result<Self> {
         // TODO: read from command line args or .config/i3-companion/config
         let config_cli = parse_cli();
-        let config_content = if let Some(config) = config_cli {
+        let config_content = if let Some(config) = config_cli.config {
             std::fs::read_to_string(config).ok()
         } else {
             config_dir().and_then(|mut path| {
@@ -159,10 +209,12 @@ impl TomlConfig {
         })
         .unwrap();
 
-        toml::from_str(config_content.as_str()).or_else(|e| {
+        let mut config: Self = toml::from_str(config_content.as_str()).unwrap_or_else(|e| {
             eprintln!("Error parsing config file:\n{}", e);
             exit(5);
-        })
+        });
+        config.restore_session = config_cli.restore_session;
+        Ok(config)
     }
 }
 impl Config {
@@ -172,12 +224,28 @@ impl Config {
         if let Some(config) = self.ws_history.take() {
             handlers.push(Box::new(config));
         }
+        if let Some(config) = self.output_history.take() {
+            handlers.push(Box::new(config));
+        }
         if let Some(config) = self.layout_tracker.take() {
             handlers.push(Box::new(config));
         }
         if let Some(config) = self.output_tracker.take() {
             handlers.push(Box::new(config));
         }
+        // Layout rules need to be handled before the modules they enable
+        if let Some(config) = self.layout_rules.take() {
+            handlers.push(Box::new(config));
+        }
+        if let Some(config) = self.autotiling.take() {
+            handlers.push(Box::new(config));
+        }
+        if let Some(config) = self.master_stack.take() {
+            handlers.push(Box::new(config));
+        }
+        if let Some(config) = self.layout_snapshot.take() {
+            handlers.push(Box::new(config));
+        }
         handlers
     }
 }
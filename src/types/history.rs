@@ -0,0 +1,356 @@
+use serde::Deserialize;
+use std::{
+    collections::{vec_deque::VecDeque, HashMap, HashSet},
+    hash::Hash,
+    ops::Index,
+    time::Instant,
+};
+
+/// Order of entries in a history stack
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum HistOrder {
+    /// Most recently visited first, with entries repeated each time they are visited
+    Recency,
+    /// Entries appear once, ordered by a score based on visit count and time spent,
+    /// decaying over time
+    Frecency,
+    /// Entries appear once, and visiting one moves it to the top
+    MoveToFront,
+}
+impl HistOrder {
+    /// Whether each entry appears at most once in the stack
+    pub fn is_unique(&self) -> bool {
+        !matches!(self, Self::Recency)
+    }
+}
+
+/// History stack of workspaces, outputs, etc., with a pointer to the entry that is currently
+/// focused while traversing it
+#[derive(Clone)]
+pub struct History<T = i32> {
+    pub hist: VecDeque<T>,
+    pub hist_ptr: usize,
+}
+impl<T: Clone + Eq + Hash> History<T> {
+    pub fn with_capacity(hist_sz: usize) -> Self {
+        Self {
+            hist: VecDeque::with_capacity(hist_sz),
+            hist_ptr: 0,
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.hist.len()
+    }
+    /// Add `entry` to the top of the stack according to `order`, resetting the pointer and
+    /// forgetting the oldest entry if the stack would grow past `hist_sz`
+    pub fn push(&mut self, entry: T, hist_sz: usize, order: HistOrder, scores: &HashMap<T, f64>) {
+        if order.is_unique() {
+            // Keep one entry per item, moving `entry` to the top
+            self.reset(order, scores);
+            self.remove(&entry);
+            self.hist.truncate(hist_sz);
+            self.hist.push_front(entry);
+            if order == HistOrder::Frecency {
+                self.sort_tail(scores);
+            }
+        } else if self.len() == 0 || self.hist[self.hist_ptr] != entry {
+            // Add `entry` to history if it won't create a duplicate
+            self.reset_ptr();
+            // Prevent duplicate sequences of 2
+            if self.len() > 2 && self.hist[0] == self.hist[2] && entry == self.hist[1] {
+                self.hist.pop_front();
+            } else {
+                // Add new entry, forgetting oldest if at max length
+                self.hist.truncate(hist_sz);
+                self.hist.push_front(entry);
+            }
+        }
+    }
+    /// Reset the history pointer, reversing the order of history before it
+    /// NOTE: may change `hist.len()`
+    pub fn reset_ptr(&mut self) {
+        if self.hist_ptr > 0 {
+            // Reverse order of history that has been cycled back through,
+            // preventing double ups
+            if self.hist_ptr < self.hist.len() - 1 && self.hist[self.hist_ptr + 1] == self.hist[0] {
+                self.hist.pop_front();
+                self.hist_ptr -= 1;
+            }
+            for i in 0..=self.hist_ptr / 2 {
+                self.hist.swap(i, self.hist_ptr - i);
+            }
+            self.hist_ptr = 0;
+        }
+    }
+    /// Reset the history pointer according to `order`
+    pub fn reset(&mut self, order: HistOrder, scores: &HashMap<T, f64>) {
+        match order {
+            HistOrder::Recency => self.reset_ptr(),
+            HistOrder::Frecency | HistOrder::MoveToFront => {
+                self.promote();
+                if order == HistOrder::Frecency {
+                    self.sort_tail(scores);
+                }
+            }
+        }
+    }
+    /// Remove all but the first entry for each item, keeping the pointer on the same item
+    pub fn unique(&mut self) {
+        let mut seen = HashSet::with_capacity(self.hist.len());
+        let mut i = 0;
+        while i < self.hist.len() {
+            if seen.insert(self.hist[i].clone()) {
+                i += 1;
+            } else {
+                let removed = self.hist.remove(i);
+                if i == self.hist_ptr {
+                    // Move to the first entry for the same item, which is the one that is kept
+                    self.hist_ptr = self
+                        .hist
+                        .iter()
+                        .position(|e| Some(e) == removed.as_ref())
+                        .unwrap_or(0);
+                } else if i < self.hist_ptr {
+                    self.hist_ptr -= 1;
+                }
+            }
+        }
+    }
+    /// Move the entry at the pointer to the top of the stack, leaving the others in place
+    pub fn promote(&mut self) {
+        if let Some(entry) = self.hist.remove(self.hist_ptr) {
+            self.hist.push_front(entry);
+        }
+        self.hist_ptr = 0;
+    }
+    /// Sort all entries below the top of the stack by descending score
+    pub fn sort_tail(&mut self, scores: &HashMap<T, f64>) {
+        if self.hist.len() > 1 {
+            let score = |entry: &T| scores.get(entry).copied().unwrap_or(0.0);
+            self.hist.make_contiguous()[1..].sort_by(|a, b| score(b).total_cmp(&score(a)));
+        }
+    }
+    /// Remove adjacent duplicate entries, keeping the pointer on the same item
+    pub fn dedup(&mut self) {
+        let mut i = 1;
+        while i < self.hist.len() {
+            if self.hist[i] == self.hist[i - 1] {
+                self.hist.remove(i);
+                if i <= self.hist_ptr {
+                    self.hist_ptr -= 1;
+                }
+            } else {
+                i += 1;
+            }
+        }
+    }
+    /// Remove all entries for `entry`.
+    /// If the pointer was on a removed entry, it moves to the next older one.
+    pub fn remove(&mut self, entry: &T) {
+        let mut i = 0;
+        while i < self.hist.len() {
+            if &self.hist[i] == entry {
+                self.hist.remove(i);
+                if i < self.hist_ptr {
+                    self.hist_ptr -= 1;
+                }
+            } else {
+                i += 1;
+            }
+        }
+        self.dedup();
+        self.hist_ptr = self.hist_ptr.min(self.hist.len().saturating_sub(1));
+    }
+    /// Replace all entries for `old` with `new`
+    pub fn rename(&mut self, old: &T, new: T) {
+        for entry in self.hist.iter_mut().filter(|entry| *entry == old) {
+            *entry = new.clone();
+        }
+        self.dedup();
+    }
+    /// Merge `other` into this stack, interleaving entries by the time they were last visited.
+    /// Resets the pointer.
+    pub fn merge(&mut self, other: &Self, last_visit: &HashMap<T, Instant>, hist_sz: usize) {
+        self.reset_ptr();
+        let mut ours = std::mem::take(&mut self.hist);
+        let mut theirs = other.hist.clone();
+        while let (Some(a), Some(b)) = (ours.front(), theirs.front()) {
+            let next = if last_visit.get(a) >= last_visit.get(b) {
+                ours.pop_front()
+            } else {
+                theirs.pop_front()
+            };
+            self.hist.extend(next);
+        }
+        self.hist.append(&mut ours);
+        self.hist.append(&mut theirs);
+        self.hist_ptr = 0;
+        self.dedup();
+        self.hist.truncate(hist_sz);
+    }
+}
+impl<T> Index<usize> for History<T> {
+    type Output = T;
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.hist[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn hist(entries: &[i32], hist_ptr: usize) -> History {
+        History {
+            hist: entries.iter().copied().collect(),
+            hist_ptr,
+        }
+    }
+    fn entries(hist: &History) -> Vec<i32> {
+        hist.hist.iter().copied().collect()
+    }
+    fn push_all(hist: &mut History, entries: &[i32], hist_sz: usize, order: HistOrder) {
+        push_scored(hist, entries, hist_sz, order, &HashMap::new());
+    }
+    fn push_scored(
+        hist: &mut History,
+        entries: &[i32],
+        hist_sz: usize,
+        order: HistOrder,
+        scores: &HashMap<i32, f64>,
+    ) {
+        for &entry in entries {
+            hist.push(entry, hist_sz, order, scores);
+        }
+    }
+
+    #[test]
+    fn push_recency() {
+        let mut h = History::with_capacity(10);
+        push_all(&mut h, &[1, 2, 3, 3], 10, HistOrder::Recency);
+        assert_eq!(entries(&h), [3, 2, 1]);
+    }
+
+    #[test]
+    fn push_recency_prevents_repeated_pairs() {
+        let mut h = History::with_capacity(10);
+        push_all(&mut h, &[1, 2, 1, 2], 10, HistOrder::Recency);
+        assert_eq!(entries(&h), [2, 1]);
+    }
+
+    #[test]
+    fn push_forgets_oldest() {
+        let mut h = History::with_capacity(3);
+        push_all(&mut h, &[1, 2, 3, 4, 5], 3, HistOrder::Recency);
+        assert_eq!(h[0], 5);
+        assert!(!h.hist.contains(&1));
+    }
+
+    #[test]
+    fn push_resets_pointer() {
+        let mut h = hist(&[1, 2, 3], 2);
+        h.push(4, 10, HistOrder::Recency, &HashMap::new());
+        assert_eq!(entries(&h), [4, 3, 2, 1]);
+        assert_eq!(h.hist_ptr, 0);
+    }
+
+    #[test]
+    fn push_move_to_front() {
+        let mut h = History::with_capacity(10);
+        push_all(&mut h, &[1, 2, 3, 1], 10, HistOrder::MoveToFront);
+        assert_eq!(entries(&h), [1, 3, 2]);
+    }
+
+    #[test]
+    fn push_frecency_sorts_tail() {
+        let scores = HashMap::from([(1, 5.0), (2, 1.0), (3, 3.0)]);
+        let mut h = History::with_capacity(10);
+        push_scored(&mut h, &[1, 2, 3], 10, HistOrder::Frecency, &scores);
+        assert_eq!(entries(&h), [3, 1, 2]);
+    }
+
+    #[test]
+    fn reset_ptr_reverses_traversed_entries() {
+        let mut h = hist(&[1, 2, 3, 4], 2);
+        h.reset_ptr();
+        assert_eq!(entries(&h), [3, 2, 1, 4]);
+        assert_eq!(h.hist_ptr, 0);
+    }
+
+    #[test]
+    fn reset_ptr_prevents_double_up() {
+        let mut h = hist(&[1, 2, 3, 1], 2);
+        h.reset_ptr();
+        assert_eq!(entries(&h), [3, 2, 1]);
+        assert_eq!(h.hist_ptr, 0);
+    }
+
+    #[test]
+    fn unique_keeps_first_entries() {
+        let mut h = hist(&[1, 2, 1, 3], 3);
+        h.unique();
+        assert_eq!(entries(&h), [1, 2, 3]);
+        assert_eq!(h.hist_ptr, 2);
+    }
+
+    #[test]
+    fn unique_keeps_pointer_on_removed_item() {
+        let mut h = hist(&[5, 3, 5], 2);
+        h.unique();
+        assert_eq!(entries(&h), [5, 3]);
+        assert_eq!(h.hist_ptr, 0);
+    }
+
+    #[test]
+    fn promote() {
+        let mut h = hist(&[1, 2, 3], 2);
+        h.promote();
+        assert_eq!(entries(&h), [3, 1, 2]);
+        assert_eq!(h.hist_ptr, 0);
+    }
+
+    #[test]
+    fn dedup_keeps_pointer_on_same_item() {
+        let mut h = hist(&[1, 1, 2, 2, 3], 3);
+        h.dedup();
+        assert_eq!(entries(&h), [1, 2, 3]);
+        assert_eq!(h.hist_ptr, 1);
+    }
+
+    #[test]
+    fn remove_moves_pointer_to_older_entry() {
+        let mut h = hist(&[1, 2, 3], 1);
+        h.remove(&2);
+        assert_eq!(entries(&h), [1, 3]);
+        assert_eq!(h.hist_ptr, 1);
+    }
+
+    #[test]
+    fn remove_merges_adjacent_entries() {
+        let mut h = hist(&[1, 2, 1, 3], 3);
+        h.remove(&2);
+        assert_eq!(entries(&h), [1, 3]);
+        assert_eq!(h.hist_ptr, 1);
+    }
+
+    #[test]
+    fn rename() {
+        let mut h = hist(&[1, 2, 3], 0);
+        h.rename(&2, 3);
+        assert_eq!(entries(&h), [1, 3]);
+    }
+
+    #[test]
+    fn merge_by_last_visit() {
+        let now = Instant::now();
+        let last_visit: HashMap<i32, Instant> = [(1, 3), (2, 1), (3, 4), (4, 0)]
+            .into_iter()
+            .map(|(entry, secs)| (entry, now + Duration::from_secs(secs)))
+            .collect();
+        let mut h = hist(&[1, 2], 0);
+        h.merge(&hist(&[3, 4], 0), &last_visit, 3);
+        assert_eq!(entries(&h), [3, 1, 2]);
+        assert_eq!(h.hist_ptr, 0);
+    }
+}
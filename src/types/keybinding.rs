@@ -2,16 +2,28 @@ use serde::{de::Visitor, Deserialize};
 use std::collections::HashSet;
 use tokio_i3ipc::event as I3Event;
 
+/// Argument that marks the i3 command of a `--release` binding, eg.
+/// `bindsym --release Super_L nop --release`.
+/// i3 doesn't report whether a binding event came from a release binding, so this is used to
+/// tell them apart.
+const RELEASE_MARKER: &str = "--release";
+
 #[derive(Clone)]
 pub struct KeyBinding {
     pub event_state_mask: HashSet<String>,
     pub symbol: Option<String>,
     pub input_type: I3Event::BindType,
+    pub release: bool,
 }
 impl PartialEq<I3Event::BindingData> for KeyBinding {
     fn eq(&self, other: &I3Event::BindingData) -> bool {
         let key = &other.binding;
+        let release = key
+            .command
+            .split_whitespace()
+            .any(|arg| arg == RELEASE_MARKER);
         self.symbol == key.symbol
+            && self.release == release
             && self.input_type == key.input_type
             && self.event_state_mask.len() == key.event_state_mask.len()
             && {
@@ -30,7 +42,7 @@ impl<'de> Visitor<'de> for KeyBindingVisitor {
     type Value = KeyBinding;
 
     fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-        formatter.write_str("a keybinding in the i3-style format (eg. Mod4+o)")
+        formatter.write_str("a keybinding in the i3-style format (eg. Mod4+o or --release Super_L)")
     }
 
     fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
@@ -39,7 +51,11 @@ impl<'de> Visitor<'de> for KeyBindingVisitor {
     {
         let mut symbol = None;
         let mut event_state_mask = HashSet::<String>::new();
-        for key in v.split("+") {
+        let (release, keys) = match v.strip_prefix(RELEASE_MARKER) {
+            Some(keys) => (true, keys.trim_start()),
+            None => (false, v),
+        };
+        for key in keys.split("+") {
             match key {
                 "Mod1" | "Mod2" | "Mod3" | "Mod4" | "ctrl" | "shift" => {
                     event_state_mask.insert(key.into());
@@ -49,7 +65,8 @@ impl<'de> Visitor<'de> for KeyBindingVisitor {
                 }
                 _ => {
                     if symbol.is_none() {
-                        symbol = Some(key.to_lowercase());
+                        // i3 reports the symbol as written in its config, eg. `Tab`
+                        symbol = Some(key.to_string());
                     } else {
                         return Err(E::custom(format!(
                             "Keybinding {} has unexpected extra symbol: {}",
@@ -63,6 +80,7 @@ impl<'de> Visitor<'de> for KeyBindingVisitor {
             event_state_mask,
             symbol,
             input_type: I3Event::BindType::Keyboard,
+            release,
         })
     }
 }
@@ -75,3 +93,40 @@ impl<'de> Deserialize<'de> for KeyBinding {
         deserializer.deserialize_str(KeyBindingVisitor)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::de::{value::Error, IntoDeserializer};
+
+    fn parse(s: &str) -> KeyBinding {
+        KeyBinding::deserialize(IntoDeserializer::<Error>::into_deserializer(s)).unwrap()
+    }
+    fn event(mask: &[&str], symbol: &str, command: &str) -> I3Event::BindingData {
+        I3Event::BindingData {
+            change: "run".into(),
+            binding: I3Event::BindingObject {
+                command: command.into(),
+                event_state_mask: mask.iter().map(|m| m.to_string()).collect(),
+                input_code: 0,
+                symbol: Some(symbol.into()),
+                input_type: I3Event::BindType::Keyboard,
+            },
+        }
+    }
+
+    #[test]
+    fn release_binding_matches_symbol_as_written() {
+        let kb = parse("--release Super_L");
+        assert!(kb == event(&[], "Super_L", "nop --release"));
+        assert!(kb != event(&[], "Super_L", "nop"));
+        assert!(kb != event(&[], "super_l", "nop --release"));
+    }
+
+    #[test]
+    fn modifiers_and_symbol() {
+        let kb = parse("Mod4+Shift+Tab");
+        assert!(kb == event(&["shift", "Mod4"], "Tab", "nop"));
+        assert!(kb != event(&["Mod4"], "Tab", "nop"));
+    }
+}
@@ -0,0 +1,207 @@
+use super::{
+    layout_tracker::I3Layout,
+    node_utils::{get_leaves, get_workspaces, WorkspaceSet},
+    traits::OnEvent,
+};
+use async_trait::async_trait;
+use regex::Regex;
+use serde::{de::Error, Deserialize, Deserializer};
+use std::{
+    collections::{HashMap, HashSet},
+    process::exit,
+};
+use tokio_i3ipc::{
+    event::{Event, Subscribe, WindowChange, WorkspaceChange},
+    reply::Node,
+    I3,
+};
+
+/// Layout or layout policy to apply to a workspace
+#[derive(Clone, Copy, Deserialize)]
+pub enum LayoutRule {
+    SplitH,
+    SplitV,
+    Stacking,
+    Tabbed,
+    Autotiling,
+    MasterStack,
+}
+impl LayoutRule {
+    /// i3 layout, if this rule is a plain i3 layout
+    fn i3_layout(&self) -> Option<I3Layout> {
+        match self {
+            Self::SplitH => Some(I3Layout::SplitH),
+            Self::SplitV => Some(I3Layout::SplitV),
+            Self::Stacking => Some(I3Layout::Stacking),
+            Self::Tabbed => Some(I3Layout::Tabbed),
+            Self::Autotiling | Self::MasterStack => None,
+        }
+    }
+}
+
+/// Rule matching workspace names
+#[derive(Deserialize)]
+pub struct WorkspaceRule {
+    #[serde(deserialize_with = "deserialize_regex")]
+    pub pattern: Regex,
+    pub layout: LayoutRule,
+}
+
+fn deserialize_regex<'de, D>(deserializer: D) -> Result<Regex, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let pattern = String::deserialize(deserializer)?;
+    Regex::new(&pattern)
+        .map_err(|e| D::Error::custom(format!("Invalid regex '{}': {}", pattern, e)))
+}
+
+/// Apply a default layout to workspaces when they are created or receive their first window
+pub struct LayoutRules {
+    pub workspaces: Vec<WorkspaceRule>,
+    pub outputs: HashMap<String, LayoutRule>,
+    pub autotiling: Option<WorkspaceSet>,
+    pub master_stack: Option<WorkspaceSet>,
+}
+
+/// Config input for `LayoutRules`
+#[derive(Deserialize)]
+pub struct LayoutRulesConfig {
+    #[serde(default)]
+    pub workspaces: Vec<WorkspaceRule>,
+    #[serde(default)]
+    pub outputs: HashMap<String, LayoutRule>,
+}
+
+impl
+    From<(
+        LayoutRulesConfig,
+        Option<WorkspaceSet>,
+        Option<WorkspaceSet>,
+    )> for LayoutRules
+{
+    fn from(
+        (config, autotiling, master_stack): (
+            LayoutRulesConfig,
+            Option<WorkspaceSet>,
+            Option<WorkspaceSet>,
+        ),
+    ) -> Self {
+        let rules = config
+            .workspaces
+            .iter()
+            .map(|r| r.layout)
+            .chain(config.outputs.values().copied());
+        for rule in rules {
+            if matches!(rule, LayoutRule::Autotiling) && autotiling.is_none() {
+                eprintln!("ERROR: Autotiling layout rule requires an [autotiling] block");
+                exit(7);
+            }
+            if matches!(rule, LayoutRule::MasterStack) && master_stack.is_none() {
+                eprintln!("ERROR: MasterStack layout rule requires a [master_stack] block");
+                exit(7);
+            }
+        }
+        Self {
+            workspaces: config.workspaces,
+            outputs: config.outputs,
+            autotiling,
+            master_stack,
+        }
+    }
+}
+
+impl LayoutRules {
+    /// Find the rule for a workspace, falling back on the default for its output
+    fn get_rule(&self, ws_name: &str, output: Option<&String>) -> Option<LayoutRule> {
+        self.workspaces
+            .iter()
+            .find(|r| r.pattern.is_match(ws_name))
+            .map(|r| r.layout)
+            .or_else(|| output.and_then(|o| self.outputs.get(o)).copied())
+    }
+
+    /// Apply the rule for workspace `ws`.
+    /// `target` is the container to apply an i3 layout to (the workspace, or a window on it).
+    fn apply(&self, ws: &Node, target: usize) -> Option<String> {
+        let ws_name = ws.name.as_ref()?;
+        let rule = self.get_rule(ws_name, ws.output.as_ref())?;
+        if let Some(layout) = rule.i3_layout() {
+            return Some(format!("[con_id={}] layout {}", target, layout.cmd()));
+        }
+        self.add_policy(ws_name, rule);
+        None
+    }
+
+    /// Hand workspace `ws_name` over to the module of a policy rule
+    fn add_policy(&self, ws_name: &str, rule: LayoutRule) {
+        let set = match rule {
+            LayoutRule::Autotiling => &self.autotiling,
+            LayoutRule::MasterStack => &self.master_stack,
+            _ => return,
+        };
+        if let Some(set) = set {
+            set.lock().unwrap().insert(ws_name.to_string());
+        }
+    }
+
+    /// Take workspaces for which `keep` returns false back from the modules of policy rules
+    fn retain_policies(&self, keep: impl Fn(&String) -> bool) {
+        for set in [&self.autotiling, &self.master_stack].into_iter().flatten() {
+            set.lock().unwrap().retain(|name| keep(name));
+        }
+    }
+}
+
+#[async_trait]
+impl OnEvent for LayoutRules {
+    fn add_subscriptions(&self, subs: &mut HashSet<u32>) {
+        subs.insert(Subscribe::Workspace.into());
+        subs.insert(Subscribe::Window.into());
+    }
+
+    async fn handle_event(&mut self, e: &Event, i3: &mut I3) -> Option<String> {
+        match e {
+            Event::Workspace(ws) if ws.change == WorkspaceChange::Init => {
+                let ws = ws.current.as_ref()?;
+                self.apply(ws, ws.id)
+            }
+            // The workspace is destroyed, so one created later with the same name starts afresh
+            Event::Workspace(ws) if ws.change == WorkspaceChange::Empty => {
+                let name = ws.current.as_ref()?.name.as_ref()?;
+                self.retain_policies(|n| n != name);
+                None
+            }
+            // Rename events don't include the old name, so drop the names that no longer exist
+            // and apply the policy for the new one (the layout of the workspace is left as is)
+            Event::Workspace(ws) if ws.change == WorkspaceChange::Rename => {
+                let names: HashSet<String> = i3
+                    .get_workspaces()
+                    .await
+                    .ok()?
+                    .into_iter()
+                    .map(|ws| ws.name)
+                    .collect();
+                self.retain_policies(|n| names.contains(n));
+                let ws = ws.current.as_ref()?;
+                let ws_name = ws.name.as_ref()?;
+                if let Some(rule) = self.get_rule(ws_name, ws.output.as_ref()) {
+                    self.add_policy(ws_name, rule);
+                }
+                None
+            }
+            Event::Window(w) if w.change == WindowChange::New => {
+                let tree = i3.get_tree().await.ok()?;
+                let ws = get_workspaces(&tree)
+                    .into_iter()
+                    .find(|ws| get_leaves(ws).iter().any(|n| n.id == w.container.id))?;
+                if get_leaves(ws).len() == 1 {
+                    self.apply(ws, w.container.id)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+}
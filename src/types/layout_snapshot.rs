@@ -0,0 +1,401 @@
+use super::{
+    keybinding::KeyBinding,
+    node_utils::{get_windows, get_workspaces, quote},
+    traits::OnEvent,
+};
+use async_trait::async_trait;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::PathBuf,
+    process::exit,
+};
+use tokio_i3ipc::{
+    event::{Event, Subscribe},
+    reply::{Node, NodeType},
+    I3,
+};
+
+/// Prefix for marks used to place existing windows into a restored layout
+const MARK_PREFIX: &str = "_i3c_restore_";
+/// Prefix of the payload of tick events that save or restore a named snapshot, eg.
+/// `i3-msg -t send_tick "i3-companion:layout_snapshot:save coding"`
+const TICK_PREFIX: &str = "i3-companion:layout_snapshot:";
+
+/// Window properties that can be used to match windows to restored placeholders
+#[derive(Clone, Copy, Deserialize)]
+pub enum SwallowCriterion {
+    Class,
+    Instance,
+    Title,
+    WindowRole,
+}
+impl SwallowCriterion {
+    /// Key used by i3 for this criterion in `append_layout` files
+    fn key(&self) -> &'static str {
+        match self {
+            Self::Class => "class",
+            Self::Instance => "instance",
+            Self::Title => "title",
+            Self::WindowRole => "window_role",
+        }
+    }
+    fn get<'a>(&self, node: &'a Node) -> Option<&'a String> {
+        let props = node.window_properties.as_ref()?;
+        match self {
+            Self::Class => props.class.as_ref(),
+            Self::Instance => props.instance.as_ref(),
+            Self::Title => props.title.as_ref(),
+            Self::WindowRole => props.window_role.as_ref(),
+        }
+    }
+}
+
+/// Save and restore workspace layouts in i3's `append_layout` format
+pub struct LayoutSnapshot {
+    restore_session: bool,
+    pub layout_dir: PathBuf,
+    pub swallow: Vec<SwallowCriterion>,
+    pub commands: HashMap<String, String>,
+    pub binding_save: Option<KeyBinding>,
+    pub binding_restore: Option<KeyBinding>,
+}
+
+// serde default values
+fn default_swallow() -> Vec<SwallowCriterion> {
+    vec![SwallowCriterion::Class, SwallowCriterion::Instance]
+}
+fn default_layout_dir() -> PathBuf {
+    dirs::data_dir()
+        .map(|mut path| {
+            path.push("i3-companion/layouts");
+            path
+        })
+        .unwrap_or_else(|| {
+            eprintln!("ERROR: could not find a data directory for saved layouts");
+            exit(9);
+        })
+}
+
+/// Config input for `LayoutSnapshot`
+#[derive(Deserialize)]
+pub struct LayoutSnapshotConfig {
+    pub layout_dir: Option<PathBuf>,
+    #[serde(default = "default_swallow")]
+    pub swallow: Vec<SwallowCriterion>,
+    #[serde(default)]
+    pub commands: HashMap<String, String>,
+    pub binding_save: Option<KeyBinding>,
+    pub binding_restore: Option<KeyBinding>,
+}
+
+impl From<(LayoutSnapshotConfig, bool)> for LayoutSnapshot {
+    fn from((config, restore_session): (LayoutSnapshotConfig, bool)) -> Self {
+        Self {
+            restore_session,
+            layout_dir: config.layout_dir.unwrap_or_else(default_layout_dir),
+            swallow: config.swallow,
+            commands: config.commands,
+            binding_save: config.binding_save,
+            binding_restore: config.binding_restore,
+        }
+    }
+}
+
+impl LayoutSnapshot {
+    /// Path of the saved layout `name`
+    fn layout_path(&self, name: &str) -> PathBuf {
+        let mut path = self.layout_dir.clone();
+        path.push(format!(
+            "{}.json",
+            name.replace('%', "%25").replace('/', "%2F")
+        ));
+        path
+    }
+
+    /// Path of the file recording which workspace the saved layout `name` was saved from
+    fn workspace_path(&self, name: &str) -> PathBuf {
+        self.layout_path(name).with_extension("workspace")
+    }
+
+    /// Workspace the saved layout `name` was saved from (layouts saved before this was recorded
+    /// are assumed to be saved under the name of their workspace)
+    fn saved_workspace(&self, name: &str) -> String {
+        fs::read_to_string(self.workspace_path(name))
+            .map(|ws| ws.trim_end_matches('\n').to_string())
+            .unwrap_or_else(|_| name.to_string())
+    }
+
+    /// Names of all saved layouts
+    fn saved_layouts(&self) -> Vec<String> {
+        let mut out: Vec<String> = fs::read_dir(&self.layout_dir)
+            .map(|dir| {
+                dir.flatten()
+                    .map(|entry| entry.path())
+                    .filter(|path| matches!(path.extension(), Some(ext) if ext == "json"))
+                    .filter_map(|path| {
+                        path.file_stem()
+                            .and_then(|s| s.to_str())
+                            .map(|s| s.replace("%2F", "/").replace("%25", "%"))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        out.sort();
+        out
+    }
+
+    /// Convert a container and its children to the `append_layout` format
+    fn serialize_node(&self, node: &Node) -> Value {
+        let mut out = Map::new();
+        out.insert("type".into(), serde_json::to_value(node.node_type).unwrap());
+        out.insert("border".into(), serde_json::to_value(&node.border).unwrap());
+        out.insert(
+            "current_border_width".into(),
+            node.current_border_width.into(),
+        );
+        if let Some(percent) = node.percent {
+            out.insert("percent".into(), percent.into());
+        }
+        if node.node_type == NodeType::FloatingCon {
+            out.insert("rect".into(), serde_json::to_value(&node.rect).unwrap());
+        }
+        if node.window.is_some() {
+            if let Some(name) = &node.name {
+                out.insert("name".into(), name.clone().into());
+            }
+            let mut criteria = Map::new();
+            for c in self.swallow.iter() {
+                if let Some(value) = c.get(node) {
+                    criteria.insert(c.key().into(), format!("^{}$", regex::escape(value)).into());
+                }
+            }
+            out.insert("swallows".into(), vec![Value::Object(criteria)].into());
+        } else {
+            out.insert("layout".into(), serde_json::to_value(node.layout).unwrap());
+            out.insert(
+                "nodes".into(),
+                node.nodes
+                    .iter()
+                    .map(|n| self.serialize_node(n))
+                    .collect::<Vec<Value>>()
+                    .into(),
+            );
+        }
+        Value::Object(out)
+    }
+
+    /// Save the layout of workspace `ws` as `name`, overwriting any previous save of that name.
+    /// The name of the workspace is recorded so that `--restore-session` can recreate it.
+    fn save(&self, ws: &Node, name: &str) -> std::io::Result<()> {
+        let content = ws
+            .nodes
+            .iter()
+            .chain(ws.floating_nodes.iter())
+            .map(|n| serde_json::to_string_pretty(&self.serialize_node(n)))
+            .collect::<Result<Vec<String>, _>>()?
+            .join("\n\n");
+        fs::create_dir_all(&self.layout_dir)?;
+        fs::write(self.layout_path(name), content)?;
+        fs::write(
+            self.workspace_path(name),
+            format!("{}\n", ws.name.as_deref().unwrap_or(name)),
+        )
+    }
+
+    /// Walk the saved layout, marking placeholders that can be filled by windows in `existing`
+    /// and collecting commands to launch the ones that can't.
+    fn place_windows(
+        &self,
+        node: &mut Value,
+        existing: &mut Vec<&Node>,
+        moves: &mut Vec<(usize, String)>,
+        launch: &mut Vec<String>,
+    ) {
+        if let Some(nodes) = node.get_mut("nodes").and_then(|n| n.as_array_mut()) {
+            for n in nodes.iter_mut() {
+                self.place_windows(n, existing, moves, launch);
+            }
+        }
+        let criteria: Vec<(String, Regex)> = match node
+            .get("swallows")
+            .and_then(|s| s.get(0))
+            .and_then(|s| s.as_object())
+        {
+            Some(criteria) => criteria
+                .iter()
+                .filter_map(|(key, value)| Some((key.clone(), Regex::new(value.as_str()?).ok()?)))
+                .collect(),
+            None => return,
+        };
+        let matches = |win: &Node| {
+            criteria.iter().all(|(key, re)| {
+                self.swallow
+                    .iter()
+                    .find(|c| c.key() == key)
+                    .and_then(|c| c.get(win))
+                    .map(|value| re.is_match(value))
+                    .unwrap_or(false)
+            })
+        };
+        if let Some(pos) = existing.iter().position(|&w| matches(w)) {
+            let mark = format!("{}{}", MARK_PREFIX, moves.len());
+            node.as_object_mut()
+                .unwrap()
+                .insert("marks".into(), vec![Value::from(mark.clone())].into());
+            moves.push((existing.remove(pos).id, mark));
+        } else if let Some((_, re)) = criteria.iter().find(|(key, _)| key == "class") {
+            if let Some(cmd) = self
+                .commands
+                .iter()
+                .find(|(class, _)| re.is_match(class))
+                .map(|(_, cmd)| cmd)
+            {
+                launch.push(cmd.clone());
+            }
+        }
+    }
+
+    /// Generate the commands to restore the saved layout `name` into workspace `ws_name`.
+    /// Windows in `existing` are moved into place (and removed from `existing`), and configured
+    /// commands are launched for the rest.
+    fn restore(&self, name: &str, ws_name: &str, existing: &mut Vec<&Node>) -> Option<String> {
+        let content = fs::read_to_string(self.layout_path(name)).ok()?;
+        let mut layout: Vec<Value> = serde_json::Deserializer::from_str(&content)
+            .into_iter::<Value>()
+            .collect::<Result<_, _>>()
+            .map_err(|e| eprintln!("Error reading saved layout '{}': {}", name, e))
+            .ok()?;
+        let mut moves = Vec::new();
+        let mut launch = Vec::new();
+        for node in layout.iter_mut() {
+            self.place_windows(node, existing, &mut moves, &mut launch);
+        }
+
+        // The marked copy is kept next to the saved layouts rather than in a shared temp dir
+        let mut tmp_path = self.layout_dir.clone();
+        tmp_path.push(".restore");
+        tmp_path.push(self.layout_path(name).file_name()?);
+        let content = layout
+            .iter()
+            .map(|n| n.to_string())
+            .collect::<Vec<String>>()
+            .join("\n");
+        if let Err(e) = fs::create_dir_all(tmp_path.parent()?).and(fs::write(&tmp_path, content)) {
+            eprintln!("Error writing layout '{}': {}", name, e);
+            return None;
+        }
+
+        let mut cmds = vec![
+            format!("workspace --no-auto-back-and-forth {}", quote(ws_name)),
+            format!("append_layout {}", quote(tmp_path.to_str()?)),
+        ];
+        for (win, mark) in moves {
+            cmds.push(format!("[con_id={}] move container to mark {}", win, mark));
+            cmds.push(format!("[con_mark=\"^{}$\"] kill", mark));
+        }
+        for cmd in launch {
+            cmds.push(format!("exec --no-startup-id {}", cmd));
+        }
+        Some(cmds.join("; "))
+    }
+}
+
+#[async_trait]
+impl OnEvent for LayoutSnapshot {
+    fn add_subscriptions(&self, subs: &mut HashSet<u32>) {
+        subs.insert(Subscribe::Binding.into());
+        subs.insert(Subscribe::Tick.into());
+    }
+
+    async fn on_connect(&mut self, i3: &mut I3) -> Option<String> {
+        if !self.restore_session {
+            return None;
+        }
+        self.restore_session = false;
+        let tree = i3.get_tree().await.ok()?;
+        let mut existing = get_windows(&tree);
+        let focused = i3
+            .get_workspaces()
+            .await
+            .ok()?
+            .into_iter()
+            .find(|ws| ws.focused)?;
+        // Each workspace gets the layout saved under its own name, or else its newest layout
+        let modified = |name: &str| {
+            fs::metadata(self.layout_path(name))
+                .and_then(|m| m.modified())
+                .ok()
+        };
+        let mut by_ws: HashMap<String, String> = HashMap::new();
+        for name in self.saved_layouts() {
+            let ws = self.saved_workspace(&name);
+            let keep = match by_ws.get(&ws) {
+                Some(cur) => *cur == ws || (name != ws && modified(cur) >= modified(&name)),
+                None => false,
+            };
+            if !keep {
+                by_ws.insert(ws, name);
+            }
+        }
+        let mut by_ws: Vec<(String, String)> = by_ws.into_iter().collect();
+        by_ws.sort();
+        let mut cmds: Vec<String> = by_ws
+            .iter()
+            .filter_map(|(ws, name)| self.restore(name, ws, &mut existing))
+            .collect();
+        if cmds.is_empty() {
+            return None;
+        }
+        cmds.push(format!(
+            "workspace --no-auto-back-and-forth {}",
+            quote(&focused.name)
+        ));
+        Some(cmds.join("; "))
+    }
+
+    async fn handle_event(&mut self, e: &Event, i3: &mut I3) -> Option<String> {
+        // Bindings save and restore under the name of the focused workspace
+        let (save, name) = match e {
+            Event::Binding(key) if matches!(&self.binding_save, Some(kb) if kb == key) => {
+                (true, None)
+            }
+            Event::Binding(key) if matches!(&self.binding_restore, Some(kb) if kb == key) => {
+                (false, None)
+            }
+            Event::Tick(tick) => {
+                let (action, name) = tick.payload.strip_prefix(TICK_PREFIX)?.split_once(' ')?;
+                let name = Some(name.trim().to_string()).filter(|n| !n.is_empty());
+                match action {
+                    "save" => (true, Some(name?)),
+                    "restore" => (false, Some(name?)),
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+        let ws_name = i3
+            .get_workspaces()
+            .await
+            .ok()?
+            .into_iter()
+            .find(|ws| ws.focused)?
+            .name;
+        let name = name.unwrap_or_else(|| ws_name.clone());
+        let tree = i3.get_tree().await.ok()?;
+        if save {
+            let ws = get_workspaces(&tree)
+                .into_iter()
+                .find(|ws| matches!(&ws.name, Some(n) if n == &ws_name))?;
+            if let Err(e) = self.save(ws, &name) {
+                eprintln!("Error saving layout '{}': {}", name, e);
+            }
+            None
+        } else {
+            self.restore(&name, &ws_name, &mut get_windows(&tree))
+        }
+    }
+}
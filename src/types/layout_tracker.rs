@@ -1,4 +1,7 @@
-use super::{pipe_sender::PipeSender, traits::OnEvent, MsgSender};
+use super::{
+    keybinding::KeyBinding, node_utils::get_focused_node, pipe_sender::PipeSender, traits::OnEvent,
+    MsgSender,
+};
 use async_trait::async_trait;
 use regex::Regex;
 use serde::Deserialize;
@@ -9,21 +12,65 @@ use std::{
 };
 use tokio_i3ipc::{
     event::{Event, Subscribe},
-    reply::Node,
+    reply::NodeLayout,
     I3,
 };
 
+/// Layouts that can be set with the i3 `layout` command
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum I3Layout {
+    SplitH,
+    SplitV,
+    Stacking,
+    Tabbed,
+}
+impl I3Layout {
+    /// Argument to the i3 `layout` command
+    pub fn cmd(&self) -> &'static str {
+        match self {
+            Self::SplitH => "splith",
+            Self::SplitV => "splitv",
+            Self::Stacking => "stacking",
+            Self::Tabbed => "tabbed",
+        }
+    }
+    /// Layout number as sent to the pipe
+    fn num(&self) -> i32 {
+        match self {
+            Self::SplitH => NodeLayout::SplitH as i32,
+            Self::SplitV => NodeLayout::SplitV as i32,
+            Self::Stacking => NodeLayout::Stacked as i32,
+            Self::Tabbed => NodeLayout::Tabbed as i32,
+        }
+    }
+}
+
 /// Layout indicator
 pub struct LayoutTracker {
     fmt_regex: Regex,
     cur_layout: i32,
     pub pipe_echo_fmt: String,
     pub pipe: Arc<dyn MsgSender + Send + Sync>,
+    pub layout_cycle: Vec<I3Layout>,
+    pub binding_cycle: Option<KeyBinding>,
 }
 #[derive(Deserialize)]
 pub struct LayoutTrackerConfig {
     pub pipe_echo_fmt: String,
     pub pipe_name: String,
+    #[serde(default = "default_layout_cycle")]
+    pub layout_cycle: Vec<I3Layout>,
+    pub binding_cycle: Option<KeyBinding>,
+}
+
+// serde default values
+fn default_layout_cycle() -> Vec<I3Layout> {
+    vec![
+        I3Layout::SplitH,
+        I3Layout::SplitV,
+        I3Layout::Stacking,
+        I3Layout::Tabbed,
+    ]
 }
 
 impl Default for LayoutTracker {
@@ -33,6 +80,8 @@ impl Default for LayoutTracker {
             cur_layout: -1,
             pipe_echo_fmt: "hook:module/i3_layout{}".into(),
             pipe: Arc::new(PipeSender::new("/tmp/polybar_mqueue.*".into())),
+            layout_cycle: default_layout_cycle(),
+            binding_cycle: None,
         }
     }
 }
@@ -63,7 +112,55 @@ impl
                     std::process::exit(6);
                 })
                 .clone(),
+            layout_cycle: config.layout_cycle,
+            binding_cycle: config.binding_cycle,
+        }
+    }
+}
+
+impl LayoutTracker {
+    /// Get the layout number that new windows will be opened into
+    async fn get_layout(i3: &mut I3) -> Option<i32> {
+        let tree = i3.get_tree().await.ok()?;
+        Some(if let Some(focused) = get_focused_node((&tree).into()) {
+            if let Some(parent) = focused.parent {
+                parent.layout as i32
+            } else {
+                focused.focused.layout as i32
+            }
+        } else {
+            6 // floating
+        })
+    }
+
+    /// Send `layout` to the pipe if it has changed
+    fn set_layout(&mut self, layout: i32) {
+        if self.cur_layout != layout {
+            self.cur_layout = layout;
+            let pipe = self.pipe.clone();
+            let msg = self
+                .fmt_regex
+                .replace_all(&self.pipe_echo_fmt[..], self.cur_layout.to_string())
+                .to_string();
+            thread::spawn(move || {
+                pipe.send(msg.as_str());
+            });
+        }
+    }
+
+    /// Switch to the layout after the current one in `layout_cycle`.
+    /// The pipe is updated immediately, since i3 doesn't send an event for layout changes.
+    async fn cycle_layout(&mut self, i3: &mut I3) -> Option<String> {
+        let layout = Self::get_layout(i3).await?;
+        if layout == 6 || self.layout_cycle.is_empty() {
+            return None;
         }
+        let next = match self.layout_cycle.iter().position(|l| l.num() == layout) {
+            Some(pos) => self.layout_cycle[(pos + 1) % self.layout_cycle.len()],
+            None => self.layout_cycle[0],
+        };
+        self.set_layout(next.num());
+        Some(format!("layout {}", next.cmd()))
     }
 }
 
@@ -73,77 +170,23 @@ impl OnEvent for LayoutTracker {
         subs.insert(Subscribe::Tick.into());
         subs.insert(Subscribe::Workspace.into());
         subs.insert(Subscribe::Window.into());
+        if self.binding_cycle.is_some() {
+            subs.insert(Subscribe::Binding.into());
+        }
     }
 
     async fn handle_event(&mut self, e: &Event, i3: &mut I3) -> Option<String> {
         match e {
             Event::Window(_) | Event::Workspace(_) | Event::Tick(_) => {
-                if let Ok(tree) = &i3.get_tree().await {
-                    let layout = if let Some(focused) = get_focused_node(tree.into()) {
-                        if let Some(parent) = focused.parent {
-                            parent.layout as i32
-                        } else {
-                            focused.focused.layout as i32
-                        }
-                    } else {
-                        6 // floating
-                    };
-                    if self.cur_layout != layout {
-                        self.cur_layout = layout;
-                        let pipe = self.pipe.clone();
-                        let msg = self
-                            .fmt_regex
-                            .replace_all(&self.pipe_echo_fmt[..], self.cur_layout.to_string())
-                            .to_string();
-                        thread::spawn(move || {
-                            pipe.send(msg.as_str());
-                        });
-                    }
+                if let Some(layout) = Self::get_layout(i3).await {
+                    self.set_layout(layout);
                 }
+                None
             }
-
-            _ => (),
-        }
-        None
-    }
-}
-
-#[derive(Debug, Clone, Copy)]
-struct FocusedNode<'a> {
-    focused: &'a Node,
-    parent: Option<&'a Node>, // Need to track parent since that contains the correct layout information
-}
-impl<'a> From<(&'a Node, &'a Node)> for FocusedNode<'a> {
-    fn from((focused, parent): (&'a Node, &'a Node)) -> Self {
-        Self {
-            focused,
-            parent: Some(parent),
-        }
-    }
-}
-impl<'a> From<&'a Node> for FocusedNode<'a> {
-    fn from(focused: &'a Node) -> Self {
-        Self {
-            focused,
-            parent: None,
-        }
-    }
-}
-fn get_focused_node<'a>(node: FocusedNode<'a>) -> Option<FocusedNode<'a>> {
-    if node.focused.focus.len() == 0 || node.focused.focused {
-        if node.focused.focused {
-            Some(node)
-        } else {
-            None // Should never happen unless there's a problem with i3
+            Event::Binding(key) if matches!(&self.binding_cycle, Some(kb) if kb == key) => {
+                self.cycle_layout(i3).await
+            }
+            _ => None,
         }
-    } else if let Some(focused) = node
-        .focused
-        .nodes
-        .iter()
-        .find(|&n| n.id == node.focused.focus[0])
-    {
-        get_focused_node((focused, node.focused).into())
-    } else {
-        None // Floating window causes this
     }
 }
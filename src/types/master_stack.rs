@@ -0,0 +1,449 @@
+use super::{
+    keybinding::KeyBinding,
+    node_utils::{
+        get_focused_node, get_leaves, get_workspaces, ws_in_set, ws_matches, WorkspaceSet,
+    },
+    traits::OnEvent,
+};
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use tokio_i3ipc::{
+    event::{BindingData, Event, Subscribe, WindowChange},
+    reply::{Node, NodeLayout},
+    I3,
+};
+
+/// Mark used to target containers when moving windows around
+const MARK: &str = "_i3c_master_stack";
+
+/// dwm-style master/stack layout for opted-in workspaces
+pub struct MasterStack {
+    ws_layouts: HashMap<String, WSLayout>,
+    pub workspaces: HashSet<String>,
+    pub rule_workspaces: WorkspaceSet,
+    pub master_count: usize,
+    pub master_ratio: f64,
+    pub ratio_step: f64,
+    pub binding_promote: Option<KeyBinding>,
+    pub binding_inc_master: Option<KeyBinding>,
+    pub binding_dec_master: Option<KeyBinding>,
+    pub binding_rotate_next: Option<KeyBinding>,
+    pub binding_rotate_prev: Option<KeyBinding>,
+    pub binding_grow_master: Option<KeyBinding>,
+    pub binding_shrink_master: Option<KeyBinding>,
+}
+
+// serde default values
+fn default_master_count() -> usize {
+    1
+}
+fn default_master_ratio() -> f64 {
+    0.5
+}
+fn default_ratio_step() -> f64 {
+    0.05
+}
+
+/// Config input for `MasterStack`
+#[derive(Deserialize)]
+pub struct MasterStackConfig {
+    #[serde(default)]
+    pub workspaces: Vec<String>,
+    #[serde(default = "default_master_count")]
+    pub master_count: usize,
+    #[serde(default = "default_master_ratio")]
+    pub master_ratio: f64,
+    #[serde(default = "default_ratio_step")]
+    pub ratio_step: f64,
+    pub binding_promote: Option<KeyBinding>,
+    pub binding_inc_master: Option<KeyBinding>,
+    pub binding_dec_master: Option<KeyBinding>,
+    pub binding_rotate_next: Option<KeyBinding>,
+    pub binding_rotate_prev: Option<KeyBinding>,
+    pub binding_grow_master: Option<KeyBinding>,
+    pub binding_shrink_master: Option<KeyBinding>,
+}
+
+impl From<MasterStackConfig> for MasterStack {
+    fn from(config: MasterStackConfig) -> Self {
+        Self {
+            ws_layouts: HashMap::new(),
+            workspaces: config.workspaces.into_iter().collect(),
+            rule_workspaces: WorkspaceSet::default(),
+            master_count: config.master_count.max(1),
+            master_ratio: config.master_ratio.clamp(0.05, 0.95),
+            ratio_step: config.ratio_step,
+            binding_promote: config.binding_promote,
+            binding_inc_master: config.binding_inc_master,
+            binding_dec_master: config.binding_dec_master,
+            binding_rotate_next: config.binding_rotate_next,
+            binding_rotate_prev: config.binding_rotate_prev,
+            binding_grow_master: config.binding_grow_master,
+            binding_shrink_master: config.binding_shrink_master,
+        }
+    }
+}
+
+/// Per-workspace layout settings, which can be changed with bindings
+#[derive(Clone, Copy)]
+struct WSLayout {
+    master_count: usize,
+    master_ratio: f64,
+}
+
+/// Master and stack columns of a workspace that is already laid out.
+/// Column ids are for the column container, or for the window itself if the column only holds
+/// a single window that hasn't been split.
+struct Columns {
+    master: usize,
+    master_split: bool,
+    masters: Vec<usize>,
+    stack: usize,
+    stack_split: bool,
+    stacked: Vec<usize>,
+}
+impl Columns {
+    /// Read the columns below `root`, ignoring the window `exclude`.
+    /// Returns `None` if `root` isn't laid out as a master and stack column.
+    fn from_tree(root: &Node, exclude: Option<usize>) -> Option<Self> {
+        let cols: Vec<&Node> = root
+            .nodes
+            .iter()
+            .filter(|n| Some(n.id) != exclude)
+            .collect();
+        if root.layout != NodeLayout::SplitH || cols.len() != 2 {
+            return None;
+        }
+        let (masters, master_split) = Self::column(cols[0], exclude)?;
+        let (stacked, stack_split) = Self::column(cols[1], exclude)?;
+        Some(Self {
+            master: cols[0].id,
+            master_split,
+            masters,
+            stack: cols[1].id,
+            stack_split,
+            stacked,
+        })
+    }
+
+    /// Get the windows in a column, and whether the column is a split container
+    fn column(node: &Node, exclude: Option<usize>) -> Option<(Vec<usize>, bool)> {
+        if node.nodes.is_empty() {
+            node.window.map(|_| (vec![node.id], false))
+        } else if node.layout == NodeLayout::SplitV && node.nodes.iter().all(|n| n.nodes.is_empty())
+        {
+            let ids: Vec<usize> = node
+                .nodes
+                .iter()
+                .filter(|n| Some(n.id) != exclude)
+                .map(|n| n.id)
+                .collect();
+            if ids.is_empty() {
+                None
+            } else {
+                Some((ids, true))
+            }
+        } else {
+            None
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.masters.len() + self.stacked.len()
+    }
+
+    /// Move `win` to the end of the stack column
+    fn push_stack(&mut self, win: usize, cmds: &mut Vec<String>) {
+        if !self.stack_split {
+            cmds.push(format!("[con_id={}] split v", self.stack));
+            self.stack_split = true;
+        }
+        move_after(win, *self.stacked.last().unwrap(), cmds);
+        self.stacked.push(win);
+    }
+
+    /// Move windows between the master and stack columns until there are `count` masters.
+    /// Returns `true` if any windows were moved.
+    fn set_master_count(&mut self, count: usize, cmds: &mut Vec<String>) -> bool {
+        let count = count.clamp(1, self.len() - 1);
+        if self.masters.len() < count {
+            if !self.master_split {
+                cmds.push(format!("[con_id={}] split v", self.master));
+                self.master_split = true;
+            }
+            while self.masters.len() < count {
+                let win = self.stacked.remove(0);
+                move_after(win, *self.masters.last().unwrap(), cmds);
+                self.masters.push(win);
+            }
+            true
+        } else if self.masters.len() > count {
+            if !self.stack_split {
+                cmds.push(format!("[con_id={}] split v", self.stack));
+                self.stack_split = true;
+            }
+            for (i, win) in self.masters.split_off(count).into_iter().enumerate() {
+                if i == 0 {
+                    move_after(win, self.stacked[0], cmds);
+                    cmds.push(format!(
+                        "[con_id={}] swap container with con_id {}",
+                        win, self.stacked[0]
+                    ));
+                } else {
+                    move_after(win, self.stacked[i - 1], cmds);
+                }
+                self.stacked.insert(i, win);
+            }
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Add commands to `cmds` to move window `win` to directly after `target`
+fn move_after(win: usize, target: usize, cmds: &mut Vec<String>) {
+    cmds.push(format!("[con_id={}] mark --add {}", target, MARK));
+    cmds.push(format!("[con_id={}] move container to mark {}", win, MARK));
+}
+
+/// Descend through containers with a single child to find the container that should hold the
+/// master and stack columns
+fn get_root(ws: &Node) -> &Node {
+    let mut root = ws;
+    while root.nodes.len() == 1 && !root.nodes[0].nodes.is_empty() {
+        root = &root.nodes[0];
+    }
+    root
+}
+
+impl MasterStack {
+    /// Check whether the layout of workspace `ws` is managed by this module
+    fn manages(&self, ws: &Node) -> bool {
+        ws_matches(&self.workspaces, ws) || ws_in_set(&self.rule_workspaces, ws)
+    }
+
+    fn get_layout(&self, ws: &Node) -> WSLayout {
+        ws.name
+            .as_ref()
+            .and_then(|name| self.ws_layouts.get(name))
+            .copied()
+            .unwrap_or(WSLayout {
+                master_count: self.master_count,
+                master_ratio: self.master_ratio,
+            })
+    }
+
+    fn get_layout_mut(&mut self, ws: &Node) -> &mut WSLayout {
+        let default = self.get_layout(ws);
+        self.ws_layouts
+            .entry(ws.name.clone().unwrap_or_default())
+            .or_insert(default)
+    }
+
+    /// Generate the commands to lay out `ws` as master and stack columns.
+    /// `new_win` is a window that has just been added to the workspace, and should go at the end
+    /// of the stack.
+    fn arrange(&self, ws: &Node, new_win: Option<usize>) -> Vec<String> {
+        let layout = self.get_layout(ws);
+        let root = get_root(ws);
+        let leaves: Vec<usize> = get_leaves(root).iter().map(|n| n.id).collect();
+        let mut cmds = Vec::new();
+        if leaves.len() < 2 {
+            return cmds;
+        }
+        let new_win = new_win.filter(|w| leaves.contains(w));
+        if let Some(mut cols) = Columns::from_tree(root, None) {
+            if cols.set_master_count(layout.master_count, &mut cmds) {
+                resize_master(cols.masters[0], layout.master_ratio, &mut cmds);
+            }
+        } else if let Some((win, mut cols)) =
+            new_win.and_then(|w| Columns::from_tree(root, Some(w)).map(|c| (w, c)))
+        {
+            cols.push_stack(win, &mut cmds);
+            cols.set_master_count(layout.master_count, &mut cmds);
+            resize_master(cols.masters[0], layout.master_ratio, &mut cmds);
+        } else {
+            let mut order: Vec<usize> = leaves
+                .iter()
+                .copied()
+                .filter(|&w| Some(w) != new_win)
+                .collect();
+            order.extend(new_win);
+            self.build(root, order, layout, &mut cmds);
+        }
+        if !cmds.is_empty() {
+            cmds.push(format!("unmark {}", MARK));
+        }
+        cmds
+    }
+
+    /// Rebuild the layout below `root` from scratch, using the window order in `order`
+    fn build(&self, root: &Node, mut order: Vec<usize>, layout: WSLayout, cmds: &mut Vec<String>) {
+        // Flatten all windows into the parent of a window that is (preferably) already a direct
+        // child of `root`, to avoid nesting containers each time the layout is rebuilt
+        if let Some(pos) = order
+            .iter()
+            .position(|&w| root.nodes.iter().any(|n| n.id == w))
+        {
+            let anchor = order.remove(pos);
+            order.insert(0, anchor);
+        }
+        for pair in order.windows(2) {
+            move_after(pair[1], pair[0], cmds);
+        }
+        cmds.push(format!("[con_id={}] layout splith", order[0]));
+        let count = layout.master_count.clamp(1, order.len() - 1);
+        for (i, column) in [&order[..count], &order[count..]].into_iter().enumerate() {
+            // A single master window can stay as it is, but the stack always needs a container
+            if i == 1 || column.len() > 1 {
+                cmds.push(format!("[con_id={}] split v", column[0]));
+            }
+            for pair in column.windows(2) {
+                move_after(pair[1], pair[0], cmds);
+            }
+        }
+        resize_master(order[0], layout.master_ratio, cmds);
+    }
+
+    /// Handle a binding event on the focused workspace
+    async fn handle_binding(&mut self, key: &BindingData, i3: &mut I3) -> Option<String> {
+        let bindings = [
+            &self.binding_promote,
+            &self.binding_inc_master,
+            &self.binding_dec_master,
+            &self.binding_rotate_next,
+            &self.binding_rotate_prev,
+            &self.binding_grow_master,
+            &self.binding_shrink_master,
+        ];
+        if !bindings.iter().any(|b| matches!(b, Some(kb) if kb == key)) {
+            return None;
+        }
+        let tree = i3.get_tree().await.ok()?;
+        let focused = get_focused_node((&tree).into())?;
+        let ws = focused.workspace?;
+        if !self.manages(ws) {
+            return None;
+        }
+        let cols = match Columns::from_tree(get_root(ws), None) {
+            Some(cols) => cols,
+            None => {
+                let cmds = self.arrange(ws, None);
+                return if cmds.is_empty() {
+                    None
+                } else {
+                    Some(cmds.join("; "))
+                };
+            }
+        };
+        let mut cmds = Vec::new();
+        if matches!(&self.binding_promote, Some(kb) if kb == key) {
+            let win = focused.focused.id;
+            let target = if win == cols.masters[0] {
+                cols.stacked[0]
+            } else {
+                cols.masters[0]
+            };
+            cmds.push(format!(
+                "[con_id={}] swap container with con_id {}",
+                win, target
+            ));
+        } else if matches!(&self.binding_inc_master, Some(kb) if kb == key) {
+            self.get_layout_mut(ws).master_count += 1;
+            cmds = self.arrange(ws, None);
+        } else if matches!(&self.binding_dec_master, Some(kb) if kb == key) {
+            let layout = self.get_layout_mut(ws);
+            layout.master_count = layout.master_count.saturating_sub(1).max(1);
+            cmds = self.arrange(ws, None);
+        } else if matches!(&self.binding_rotate_next, Some(kb) if kb == key) {
+            let wins: Vec<usize> = cols
+                .masters
+                .iter()
+                .chain(cols.stacked.iter())
+                .copied()
+                .collect();
+            for w in wins[1..].iter() {
+                cmds.push(format!(
+                    "[con_id={}] swap container with con_id {}",
+                    wins[0], w
+                ));
+            }
+        } else if matches!(&self.binding_rotate_prev, Some(kb) if kb == key) {
+            let wins: Vec<usize> = cols
+                .masters
+                .iter()
+                .chain(cols.stacked.iter())
+                .copied()
+                .collect();
+            let last = wins[wins.len() - 1];
+            for w in wins[..wins.len() - 1].iter().rev() {
+                cmds.push(format!(
+                    "[con_id={}] swap container with con_id {}",
+                    last, w
+                ));
+            }
+        } else if matches!(&self.binding_grow_master, Some(kb) if kb == key) {
+            let step = self.ratio_step;
+            let layout = self.get_layout_mut(ws);
+            layout.master_ratio = (layout.master_ratio + step).min(0.95);
+            resize_master(cols.masters[0], layout.master_ratio, &mut cmds);
+        } else if matches!(&self.binding_shrink_master, Some(kb) if kb == key) {
+            let step = self.ratio_step;
+            let layout = self.get_layout_mut(ws);
+            layout.master_ratio = (layout.master_ratio - step).max(0.05);
+            resize_master(cols.masters[0], layout.master_ratio, &mut cmds);
+        }
+        if cmds.is_empty() {
+            None
+        } else {
+            Some(cmds.join("; "))
+        }
+    }
+}
+
+/// Add a command to `cmds` to resize the master column containing window `master`
+fn resize_master(master: usize, ratio: f64, cmds: &mut Vec<String>) {
+    cmds.push(format!(
+        "[con_id={}] resize set width {} ppt",
+        master,
+        (ratio * 100.0).round() as i32
+    ));
+}
+
+#[async_trait]
+impl OnEvent for MasterStack {
+    fn add_subscriptions(&self, subs: &mut HashSet<u32>) {
+        subs.insert(Subscribe::Window.into());
+        subs.insert(Subscribe::Binding.into());
+    }
+
+    async fn handle_event(&mut self, e: &Event, i3: &mut I3) -> Option<String> {
+        match e {
+            Event::Window(w)
+                if matches!(
+                    w.change,
+                    WindowChange::New
+                        | WindowChange::Close
+                        | WindowChange::Move
+                        | WindowChange::Floating
+                ) =>
+            {
+                let tree = i3.get_tree().await.ok()?;
+                let cmds: Vec<String> = get_workspaces(&tree)
+                    .into_iter()
+                    .filter(|ws| self.manages(ws))
+                    .flat_map(|ws| self.arrange(ws, Some(w.container.id)))
+                    .collect();
+                if cmds.is_empty() {
+                    None
+                } else {
+                    Some(cmds.join("; "))
+                }
+            }
+            Event::Binding(key) => self.handle_binding(key, i3).await,
+            _ => None,
+        }
+    }
+}
@@ -1,15 +1,25 @@
 use serde::Deserialize;
 
+pub mod autotiling;
+pub mod config;
+pub mod history;
 pub mod keybinding;
+pub mod layout_rules;
+pub mod layout_snapshot;
 pub mod layout_tracker;
+pub mod master_stack;
+pub mod node_utils;
+pub mod notifier;
+pub mod output_history;
 pub mod output_tracker;
+pub mod own_commands;
 pub mod parsable_duration;
 pub mod pipe_sender;
 pub mod shell_caller;
 pub mod traits;
 pub mod ws_history;
-pub mod config;
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Deserialize)]
 pub enum SenderType {
     SHELL,
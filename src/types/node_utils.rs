@@ -0,0 +1,165 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+use tokio_i3ipc::reply::{Node, NodeType, ScratchpadState};
+
+#[derive(Debug, Clone, Copy)]
+pub struct FocusedNode<'a> {
+    pub focused: &'a Node,
+    pub parent: Option<&'a Node>, // Need to track parent since that contains the correct layout information
+    pub workspace: Option<&'a Node>,
+    /// Number of containers between the workspace and the focused node (inclusive of the focused
+    /// node). Only meaningful if `workspace` is `Some(..)`.
+    pub depth: usize,
+}
+impl<'a> FocusedNode<'a> {
+    /// Step down the tree to `child`, keeping track of the workspace and depth
+    fn child(&self, child: &'a Node) -> Self {
+        let (workspace, depth) = if self.focused.node_type == NodeType::Workspace {
+            (Some(self.focused), 1)
+        } else {
+            (self.workspace, self.depth + 1)
+        };
+        Self {
+            focused: child,
+            parent: Some(self.focused),
+            workspace,
+            depth,
+        }
+    }
+}
+impl<'a> From<&'a Node> for FocusedNode<'a> {
+    fn from(focused: &'a Node) -> Self {
+        Self {
+            focused,
+            parent: None,
+            workspace: None,
+            depth: 0,
+        }
+    }
+}
+
+/// Follow the focus stack down from `node` to find the focused tiling container.
+/// Returns `None` if a floating window is focused.
+pub fn get_focused_node(node: FocusedNode<'_>) -> Option<FocusedNode<'_>> {
+    if node.focused.focus.is_empty() || node.focused.focused {
+        if node.focused.focused {
+            Some(node)
+        } else {
+            None // Should never happen unless there's a problem with i3
+        }
+    } else if let Some(focused) = node
+        .focused
+        .nodes
+        .iter()
+        .find(|&n| n.id == node.focused.focus[0])
+    {
+        get_focused_node(node.child(focused))
+    } else {
+        None // Floating window causes this
+    }
+}
+
+/// Names of workspaces that a module has been enabled on at runtime (eg. by layout rules)
+pub type WorkspaceSet = Arc<Mutex<HashSet<String>>>;
+
+/// Check whether the workspace `node` is in `set`
+pub fn ws_in_set(set: &WorkspaceSet, node: &Node) -> bool {
+    matches!(&node.name, Some(name) if set.lock().unwrap().contains(name))
+}
+
+/// Check whether the workspace `node` matches any of the names or numbers in `names`
+pub fn ws_matches(names: &HashSet<String>, node: &Node) -> bool {
+    matches!(&node.name, Some(name) if names.contains(name))
+        || matches!(node.num, Some(num) if names.contains(&num.to_string()))
+}
+
+/// Collect all workspace nodes in the tree below `node`, excluding the scratchpad
+pub fn get_workspaces(node: &Node) -> Vec<&Node> {
+    let mut out = Vec::new();
+    collect_workspaces(node, &mut out);
+    out
+}
+fn collect_workspaces<'a>(node: &'a Node, out: &mut Vec<&'a Node>) {
+    for n in node.nodes.iter() {
+        if n.node_type == NodeType::Workspace {
+            if !matches!(&n.name, Some(name) if name == "__i3_scratch") {
+                out.push(n);
+            }
+        } else {
+            collect_workspaces(n, out);
+        }
+    }
+}
+
+/// Collect the tiled windows below `node`, in tree order
+pub fn get_leaves(node: &Node) -> Vec<&Node> {
+    let mut out = Vec::new();
+    collect_leaves(node, &mut out);
+    out
+}
+fn collect_leaves<'a>(node: &'a Node, out: &mut Vec<&'a Node>) {
+    for n in node.nodes.iter() {
+        if n.nodes.is_empty() {
+            if n.window.is_some() {
+                out.push(n);
+            }
+        } else {
+            collect_leaves(n, out);
+        }
+    }
+}
+
+/// Collect all windows below `node` (tiled and floating), in tree order
+pub fn get_windows(node: &Node) -> Vec<&Node> {
+    let mut out = Vec::new();
+    collect_windows(node, &mut out);
+    out
+}
+fn collect_windows<'a>(node: &'a Node, out: &mut Vec<&'a Node>) {
+    for n in node.nodes.iter().chain(node.floating_nodes.iter()) {
+        if n.window.is_some() {
+            out.push(n);
+        } else {
+            collect_windows(n, out);
+        }
+    }
+}
+
+/// Check whether window `id` below `node` belongs to the scratchpad. Returns `None` if it
+/// doesn't, otherwise `Some(true)` if it is shown on a workspace or `Some(false)` if it is hidden.
+pub fn scratchpad_shown(node: &Node, id: usize) -> Option<bool> {
+    for n in node.nodes.iter() {
+        if n.node_type == NodeType::Workspace {
+            let found = n.floating_nodes.iter().any(|f| {
+                f.scratchpad_state != ScratchpadState::None
+                    && (f.id == id || get_windows(f).iter().any(|w| w.id == id))
+            });
+            if found {
+                return Some(!matches!(&n.name, Some(name) if name == "__i3_scratch"));
+            }
+        } else if let Some(shown) = scratchpad_shown(n, id) {
+            return Some(shown);
+        }
+    }
+    None
+}
+
+/// Find the window that has focus within `node`, following its focus order
+pub fn get_focused_window(node: &Node) -> Option<&Node> {
+    if node.window.is_some() {
+        return Some(node);
+    }
+    let id = node.focus.first()?;
+    node.nodes
+        .iter()
+        .chain(node.floating_nodes.iter())
+        .find(|n| n.id == *id)
+        .and_then(get_focused_window)
+}
+
+/// Quote a string for use as an argument in an i3 command
+pub fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -0,0 +1,273 @@
+use std::{
+    collections::HashMap,
+    process::{exit, Stdio},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use serde::Deserialize;
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    process::Command,
+};
+
+/// How long to wait for a notification command to print the id of its notification. The command
+/// itself is left running, eg. for `notify-send --wait`.
+const COMMAND_ID_TIMEOUT: Duration = Duration::from_millis(500);
+
+use super::MsgSender;
+
+/// Where notifications are shown. Modules should show notifications through this instead of
+/// calling a notification library directly.
+#[derive(Default)]
+pub enum Notifier {
+    /// Desktop notification over D-Bus
+    #[default]
+    DBus,
+    /// External command such as `notify-send` or `dunstify`. Placeholders in its arguments are
+    /// filled in for each notification (see `command_args`).
+    Command {
+        cmd: Vec<String>,
+    },
+    /// Any sender from the `[pipes]` block
+    Pipe {
+        pipe: Arc<dyn MsgSender + Send + Sync>,
+        pipe_echo_fmt: String,
+        line_separator: String,
+    },
+    Stderr,
+}
+
+/// Config input for `Notifier`
+#[derive(Deserialize)]
+#[serde(tag = "backend")]
+pub enum NotifierConfig {
+    DBus,
+    Command {
+        command: String,
+    },
+    Pipe {
+        pipe_name: String,
+        #[serde(default = "default_pipe_echo_fmt")]
+        pipe_echo_fmt: String,
+        #[serde(default = "default_line_separator")]
+        line_separator: String,
+    },
+    Stderr,
+}
+
+// serde default values
+fn default_pipe_echo_fmt() -> String {
+    "{summary}: {body}".into()
+}
+fn default_line_separator() -> String {
+    " | ".into()
+}
+
+impl
+    From<(
+        NotifierConfig,
+        Option<&HashMap<String, Arc<dyn MsgSender + Send + Sync>>>,
+    )> for Notifier
+{
+    fn from(
+        (config, pipes): (
+            NotifierConfig,
+            Option<&HashMap<String, Arc<dyn MsgSender + Send + Sync>>>,
+        ),
+    ) -> Self {
+        match config {
+            NotifierConfig::DBus => Self::DBus,
+            NotifierConfig::Command { command } => Self::Command {
+                cmd: match shellwords::split(&command) {
+                    Ok(args) if !args.is_empty() => args,
+                    _ => {
+                        eprintln!("ERROR: invalid notification command: {}", command);
+                        exit(12);
+                    }
+                },
+            },
+            NotifierConfig::Pipe {
+                pipe_name,
+                pipe_echo_fmt,
+                line_separator,
+            } => Self::Pipe {
+                pipe: pipes
+                    .and_then(|p| p.get(&pipe_name))
+                    .unwrap_or_else(|| {
+                        eprintln!("ERROR: pipe '{}' not found in config file", pipe_name);
+                        exit(6);
+                    })
+                    .clone(),
+                pipe_echo_fmt,
+                line_separator,
+            },
+            NotifierConfig::Stderr => Self::Stderr,
+        }
+    }
+}
+
+impl Notifier {
+    /// Show a notification, replacing notification `replaces` if the backend supports it.
+    /// The notification closes after `timeout` if given, otherwise after the backend's default.
+    /// Returns the id of the notification shown, if the backend has one.
+    pub async fn notify(
+        &self,
+        summary: &str,
+        body: &str,
+        timeout: Option<Duration>,
+        replaces: Option<u32>,
+    ) -> Option<u32> {
+        match self {
+            Self::DBus => {
+                let mut notification = notify_rust::Notification::new();
+                notification
+                    .summary(summary)
+                    .body(body)
+                    .appname("i3-companion");
+                if let Some(id) = replaces {
+                    notification.id(id);
+                }
+                if let Some(timeout) = timeout {
+                    notification.timeout(timeout);
+                }
+                notification
+                    .show()
+                    .map_err(|e| eprintln!("Error showing notification: {}", e))
+                    .ok()
+                    .map(|handle| handle.id())
+            }
+            Self::Command { cmd } => {
+                let mut child = Command::new(&cmd[0])
+                    .args(command_args(&cmd[1..], summary, body, timeout, replaces))
+                    .stdout(Stdio::piped())
+                    .spawn()
+                    .map_err(|e| {
+                        eprintln!(
+                            "WARNING: error executing command `{}` -> {}",
+                            cmd.join(" "),
+                            e
+                        )
+                    })
+                    .ok()?;
+                let stdout = child.stdout.take()?;
+                tokio::spawn(async move { child.wait().await });
+                let mut line = String::new();
+                tokio::time::timeout(
+                    COMMAND_ID_TIMEOUT,
+                    BufReader::new(stdout).read_line(&mut line),
+                )
+                .await
+                .ok()?
+                .ok()?;
+                line.trim().parse().ok()
+            }
+            Self::Pipe {
+                pipe,
+                pipe_echo_fmt,
+                line_separator,
+            } => {
+                let msg = pipe_echo_fmt.replace("{summary}", summary).replace(
+                    "{body}",
+                    &body
+                        .lines()
+                        .filter(|line| !line.is_empty())
+                        .collect::<Vec<&str>>()
+                        .join(line_separator),
+                );
+                let pipe = pipe.clone();
+                thread::spawn(move || {
+                    pipe.send(msg.as_str());
+                });
+                None
+            }
+            Self::Stderr => {
+                eprintln!("{}\n{}", summary, body);
+                None
+            }
+        }
+    }
+}
+
+/// Fill in the placeholders of the arguments of a notification command: `{summary}`, `{body}`,
+/// `{timeout}` (in milliseconds) and `{id}` (of the notification to replace). Arguments with a
+/// placeholder that has no value for this notification are left out, along with the option
+/// before them (eg. `-r` in `-r {id}`). If neither `{summary}` nor `{body}` is used, the summary
+/// and body are passed as the last two arguments.
+fn command_args(
+    args: &[String],
+    summary: &str,
+    body: &str,
+    timeout: Option<Duration>,
+    replaces: Option<u32>,
+) -> Vec<String> {
+    let timeout = timeout.map(|t| t.as_millis().to_string());
+    let replaces = replaces.map(|id| id.to_string());
+    let values = [
+        ("{summary}", Some(summary)),
+        ("{body}", Some(body)),
+        ("{timeout}", timeout.as_deref()),
+        ("{id}", replaces.as_deref()),
+    ];
+    let has_placeholder = |arg: &str| values.iter().any(|(p, _)| arg.contains(p));
+    let mut out: Vec<String> = Vec::with_capacity(args.len() + 2);
+    for (i, arg) in args.iter().enumerate() {
+        let filled = values
+            .iter()
+            .filter(|(placeholder, _)| arg.contains(placeholder))
+            .try_fold(arg.clone(), |arg, (placeholder, value)| {
+                Some(arg.replace(placeholder, (*value)?))
+            });
+        match filled {
+            Some(arg) => out.push(arg),
+            None if i > 0 && args[i - 1].starts_with('-') && !has_placeholder(&args[i - 1]) => {
+                out.pop();
+            }
+            None => {}
+        }
+    }
+    if !args
+        .iter()
+        .any(|arg| arg.contains("{summary}") || arg.contains("{body}"))
+    {
+        out.extend([summary.to_string(), body.to_string()]);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(cmd: &str, timeout: Option<u64>, replaces: Option<u32>) -> Vec<String> {
+        let cmd = shellwords::split(cmd).unwrap();
+        command_args(
+            &cmd,
+            "Summary",
+            "Body",
+            timeout.map(Duration::from_millis),
+            replaces,
+        )
+    }
+
+    #[test]
+    fn placeholders_are_filled() {
+        assert_eq!(
+            args("-p -t {timeout} --replace={id}", Some(1500), Some(7)),
+            ["-p", "-t", "1500", "--replace=7", "Summary", "Body"]
+        );
+        assert_eq!(
+            args("-a app {summary} '{body}!'", None, None),
+            ["-a", "app", "Summary", "Body!"]
+        );
+    }
+
+    #[test]
+    fn missing_values_drop_their_option() {
+        assert_eq!(
+            args("-p -r {id} -u low --timeout={timeout}", None, None),
+            ["-p", "-u", "low", "Summary", "Body"]
+        );
+    }
+}
@@ -0,0 +1,239 @@
+use super::{
+    history::{HistOrder, History},
+    keybinding::KeyBinding,
+    node_utils::quote,
+    own_commands::OwnCommands,
+    parsable_duration::ParsableDuration,
+    traits::OnEvent,
+    ws_history::WSDirection,
+};
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+use tokio_i3ipc::{
+    event::{Event, Subscribe, WindowChange, WorkspaceChange},
+    I3,
+};
+
+/// History stack of focused outputs, for going back and forth between monitors
+pub struct OutputHistory {
+    hist: History<String>,
+    pub hist_sz: usize,
+    own_cmds: OwnCommands,
+    /// Output that currently has focus
+    cur_output: Option<String>,
+    /// When the pointer should next be reset
+    reset_at: Option<Instant>,
+    pub activity_timeout: Option<Duration>,
+    pub binding_prev: Option<KeyBinding>,
+    pub binding_next: Option<KeyBinding>,
+    pub binding_move_prev: Option<KeyBinding>,
+    pub binding_move_ws_prev: Option<KeyBinding>,
+}
+
+// serde default values
+fn default_hist_sz() -> usize {
+    10
+}
+
+/// Config input for `OutputHistory`
+#[derive(Deserialize)]
+pub struct OutputHistoryConfig {
+    #[serde(default = "default_hist_sz")]
+    pub hist_sz: usize,
+    pub activity_timeout: Option<ParsableDuration>,
+    pub binding_prev: Option<KeyBinding>,
+    pub binding_next: Option<KeyBinding>,
+    pub binding_move_prev: Option<KeyBinding>,
+    pub binding_move_ws_prev: Option<KeyBinding>,
+}
+
+impl From<OutputHistoryConfig> for OutputHistory {
+    fn from(config: OutputHistoryConfig) -> Self {
+        Self {
+            hist: History::with_capacity(config.hist_sz),
+            hist_sz: config.hist_sz,
+            own_cmds: OwnCommands::new("output_history"),
+            cur_output: None,
+            reset_at: None,
+            activity_timeout: config.activity_timeout.map(|d| d.into()),
+            binding_prev: config.binding_prev,
+            binding_next: config.binding_next,
+            binding_move_prev: config.binding_move_prev,
+            binding_move_ws_prev: config.binding_move_ws_prev,
+        }
+    }
+}
+
+impl OutputHistory {
+    /// Record that `output` has been focused. Focus changes caused by our own commands only
+    /// update the current output.
+    fn focus(&mut self, output: &str) {
+        if self.cur_output.as_deref() == Some(output) {
+            return;
+        }
+        self.cur_output = Some(output.to_string());
+        if !self.own_cmds.is_active() {
+            self.reset_at = None;
+            self.hist.push(
+                output.to_string(),
+                self.hist_sz,
+                HistOrder::Recency,
+                &HashMap::new(),
+            );
+        }
+    }
+
+    /// Move the pointer to the next or previous output in the stack, skipping over the
+    /// focused one.
+    /// Returns `Some(output)` if there is one available in that direction.
+    fn goto_output(&mut self, dir: WSDirection) -> Option<String> {
+        let mut ptr = self.hist.hist_ptr;
+        loop {
+            if (dir == WSDirection::NEXT && ptr == 0)
+                || (dir == WSDirection::PREV && ptr + 1 >= self.hist.len())
+            {
+                return None;
+            }
+            ptr += dir;
+            if self.cur_output.as_ref() != Some(&self.hist[ptr]) {
+                break;
+            }
+        }
+        self.hist.hist_ptr = ptr;
+        self.reset_at = self.activity_timeout.map(|t| Instant::now() + t);
+        Some(self.hist[ptr].clone())
+    }
+
+    /// Remove disconnected outputs from the stack
+    async fn update_outputs(&mut self, i3: &mut I3) {
+        let active: HashSet<String> = match i3.get_outputs().await {
+            Ok(outputs) => outputs
+                .into_iter()
+                .filter(|o| o.active)
+                .map(|o| o.name)
+                .collect(),
+            Err(_) => return,
+        };
+        let gone: Vec<String> = self
+            .hist
+            .hist
+            .iter()
+            .filter(|o| !active.contains(*o))
+            .cloned()
+            .collect();
+        for output in gone {
+            self.hist.remove(&output);
+        }
+    }
+
+    /// Output of the focused workspace
+    async fn focused_output(i3: &mut I3) -> Option<String> {
+        i3.get_workspaces()
+            .await
+            .ok()?
+            .into_iter()
+            .find(|ws| ws.focused)
+            .map(|ws| ws.output)
+    }
+}
+
+#[async_trait]
+impl OnEvent for OutputHistory {
+    fn add_subscriptions(&self, subs: &mut HashSet<u32>) {
+        subs.insert(Subscribe::Workspace as u32);
+        subs.insert(Subscribe::Window as u32);
+        subs.insert(Subscribe::Output as u32);
+        subs.insert(Subscribe::Binding as u32);
+        self.own_cmds.add_subscriptions(subs);
+    }
+
+    async fn on_connect(&mut self, i3: &mut I3) -> Option<String> {
+        self.own_cmds.clear();
+        self.update_outputs(i3).await;
+        if let Some(output) = Self::focused_output(i3).await {
+            self.focus(&output);
+        }
+        None
+    }
+
+    async fn handle_event(&mut self, e: &Event, i3: &mut I3) -> Option<String> {
+        let cmd = match e {
+            Event::Workspace(ws) => {
+                // Moving the focused workspace also moves focus to its new output
+                let focused = match ws.change {
+                    WorkspaceChange::Focus => ws.current.as_ref(),
+                    WorkspaceChange::Move => ws.current.as_ref().filter(|c| c.focused),
+                    _ => None,
+                };
+                if let Some(output) = focused.and_then(|c| c.output.as_ref()) {
+                    self.focus(output);
+                }
+                None
+            }
+            Event::Window(w) if w.change == WindowChange::Focus => {
+                let output = match &w.container.output {
+                    Some(output) => Some(output.clone()),
+                    None => Self::focused_output(i3).await,
+                };
+                if let Some(output) = output {
+                    self.focus(&output);
+                }
+                None
+            }
+            Event::Output(_) => {
+                self.update_outputs(i3).await;
+                None
+            }
+            Event::Tick(tick) => {
+                self.own_cmds.handle_tick(&tick.payload);
+                None
+            }
+            Event::Binding(key) => {
+                if matches!(&self.binding_prev, Some(kb) if kb == key) {
+                    self.goto_output(WSDirection::PREV)
+                        .map(|output| format!("focus output {}", quote(&output)))
+                } else if matches!(&self.binding_next, Some(kb) if kb == key) {
+                    self.goto_output(WSDirection::NEXT)
+                        .map(|output| format!("focus output {}", quote(&output)))
+                } else if matches!(&self.binding_move_prev, Some(kb) if kb == key) {
+                    self.goto_output(WSDirection::PREV).map(|output| {
+                        format!(
+                            "move container to output {0}; focus output {0}",
+                            quote(&output)
+                        )
+                    })
+                } else if matches!(&self.binding_move_ws_prev, Some(kb) if kb == key) {
+                    self.goto_output(WSDirection::PREV)
+                        .map(|output| format!("move workspace to output {}", quote(&output)))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+        if let Some(cmd) = cmd {
+            self.own_cmds.run(&cmd, i3).await;
+        }
+        None
+    }
+
+    fn next_timeout(&self) -> Option<Instant> {
+        self.reset_at
+            .into_iter()
+            .chain(self.own_cmds.expiry())
+            .min()
+    }
+
+    async fn on_timeout(&mut self, _i3: &mut I3) -> Option<String> {
+        self.own_cmds.expire();
+        if matches!(self.reset_at, Some(t) if t <= Instant::now()) {
+            self.reset_at = None;
+            self.hist.reset_ptr();
+        }
+        None
+    }
+}
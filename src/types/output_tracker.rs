@@ -30,8 +30,18 @@ pub struct OutputTrackerConfig {
     pub update_interval: Option<ParsableDuration>,
 }
 
-impl From<(OutputTrackerConfig, &HashMap<String, Arc<dyn MsgSender + Send + Sync>>)> for OutputTracker {
-    fn from((config, pipes): (OutputTrackerConfig, &HashMap<String, Arc<dyn MsgSender + Send + Sync>>)) -> Self {
+impl
+    From<(
+        OutputTrackerConfig,
+        &HashMap<String, Arc<dyn MsgSender + Send + Sync>>,
+    )> for OutputTracker
+{
+    fn from(
+        (config, pipes): (
+            OutputTrackerConfig,
+            &HashMap<String, Arc<dyn MsgSender + Send + Sync>>,
+        ),
+    ) -> Self {
         let out = Self {
             ipc_str: config.ipc_str,
             pipe: pipes
@@ -0,0 +1,111 @@
+use std::{
+    collections::HashSet,
+    time::{Duration, Instant},
+};
+use tokio_i3ipc::{event::Subscribe, msg::Msg, reply, I3};
+
+/// Time after which the events of our own command are assumed to be finished if the closing
+/// tick hasn't arrived
+const OWN_CMD_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Runs a module's own commands on i3, bracketed by tick events so that the events they cause
+/// can be told apart from the user's own changes. i3 handles messages in order, so all of the
+/// events caused by a command arrive between its two ticks.
+/// Modules using it need to add its subscriptions, pass it their tick events, and call `expire`
+/// from `on_timeout` if `expiry` is one of their timeouts.
+pub struct OwnCommands {
+    /// Prefix of the payload of the bracketing tick events
+    tick_prefix: String,
+    /// Id of the last command sent by `run`
+    cmd_id: u64,
+    /// Id of the command whose events are currently being received, and when they started
+    active: Option<(u64, Instant)>,
+}
+impl OwnCommands {
+    /// `module` tells apart the ticks of different modules
+    pub fn new(module: &str) -> Self {
+        Self {
+            tick_prefix: format!("i3-companion:{}:", module),
+            cmd_id: 0,
+            active: None,
+        }
+    }
+
+    /// Subscribe to the tick events bracketing our own commands
+    pub fn add_subscriptions(&self, subs: &mut HashSet<u32>) {
+        subs.insert(Subscribe::Tick as u32);
+    }
+
+    /// Run `cmd` on i3 between a pair of tick events
+    pub async fn run(&mut self, cmd: &str, i3: &mut I3) {
+        self.cmd_id += 1;
+        let tick = |stage: &str| format!("{}{}:{}", self.tick_prefix, stage, self.cmd_id);
+        if let Err(e) = i3
+            .send_read::<_, reply::Success>(Msg::Tick, tick("begin"))
+            .await
+        {
+            eprintln!("Error sending message to i3: {}", e);
+            return;
+        }
+        match i3.run_command(cmd).await {
+            Ok(replies) => {
+                for reply in replies.iter().filter(|r| !r.success) {
+                    eprintln!(
+                        "WARNING: command `{}` failed: {}",
+                        cmd,
+                        reply.error.as_deref().unwrap_or("unknown error")
+                    );
+                }
+            }
+            Err(e) => eprintln!("Error sending message to i3: {}", e),
+        }
+        // If this fails, `OWN_CMD_TIMEOUT` stops events being ignored
+        if let Err(e) = i3
+            .send_read::<_, reply::Success>(Msg::Tick, tick("end"))
+            .await
+        {
+            eprintln!("Error sending message to i3: {}", e);
+        }
+    }
+
+    /// Track the start and end of the events caused by our own commands
+    pub fn handle_tick(&mut self, payload: &str) {
+        let (stage, id) = match payload
+            .strip_prefix(self.tick_prefix.as_str())
+            .and_then(|p| p.split_once(':'))
+            .and_then(|(stage, id)| Some((stage, id.parse::<u64>().ok()?)))
+        {
+            Some(tick) => tick,
+            None => return,
+        };
+        match stage {
+            "begin" => self.active = Some((id, Instant::now())),
+            "end" if matches!(self.active, Some((cur, _)) if cur == id) => self.active = None,
+            _ => {}
+        }
+    }
+
+    /// Whether the events currently being received were caused by our own command.
+    /// Self-heals if the closing tick of a command never arrives.
+    pub fn is_active(&mut self) -> bool {
+        if matches!(self.active, Some((_, since)) if since.elapsed() > OWN_CMD_TIMEOUT) {
+            self.active = None;
+        }
+        self.active.is_some()
+    }
+
+    /// When the current command's events will be assumed to be finished, if one is active
+    pub fn expiry(&self) -> Option<Instant> {
+        self.active.map(|(_, since)| since + OWN_CMD_TIMEOUT)
+    }
+
+    /// Expire a lost closing tick once `expiry` has passed, even if no more events arrive
+    pub fn expire(&mut self) {
+        self.is_active();
+    }
+
+    /// Forget the current command, eg. after reconnecting to i3
+    pub fn clear(&mut self) {
+        self.active = None;
+    }
+}
@@ -8,7 +8,7 @@ use std::time::Duration;
 pub struct ParsableDuration(Duration);
 impl From<Duration> for ParsableDuration {
     fn from(d: Duration) -> Self {
-        Self { 0: d }
+        Self(d)
     }
 }
 impl From<ParsableDuration> for Duration {
@@ -44,24 +44,16 @@ impl<'de> Visitor<'de> for ParsableDurationVisitor {
                         v
                     ))))?;
             match caps.get(2).unwrap().as_str() {
-                "ns" => Ok(ParsableDuration {
-                    0: Duration::from_secs_f32(dur * 1000.0 * 1000.0 * 1000.0),
-                }),
-                "us" => Ok(ParsableDuration {
-                    0: Duration::from_secs_f32(dur * 1000.0 * 1000.0),
-                }),
-                "ms" => Ok(ParsableDuration {
-                    0: Duration::from_secs_f32(dur * 1000.0),
-                }),
-                "s" => Ok(ParsableDuration {
-                    0: Duration::from_secs_f32(dur),
-                }),
-                "m" => Ok(ParsableDuration {
-                    0: Duration::from_secs_f32(dur / 60.0),
-                }),
-                "h" => Ok(ParsableDuration {
-                    0: Duration::from_secs_f32(dur / 3600.0),
-                }),
+                "ns" => Ok(ParsableDuration(Duration::from_secs_f32(
+                    dur * 1000.0 * 1000.0 * 1000.0,
+                ))),
+                "us" => Ok(ParsableDuration(Duration::from_secs_f32(
+                    dur * 1000.0 * 1000.0,
+                ))),
+                "ms" => Ok(ParsableDuration(Duration::from_secs_f32(dur * 1000.0))),
+                "s" => Ok(ParsableDuration(Duration::from_secs_f32(dur))),
+                "m" => Ok(ParsableDuration(Duration::from_secs_f32(dur / 60.0))),
+                "h" => Ok(ParsableDuration(Duration::from_secs_f32(dur / 3600.0))),
                 u => Err(Error::custom(format!("Unrecognised time units: {}", u))),
             }
         } else {
@@ -1,12 +1,53 @@
 use async_trait::async_trait;
-use std::{collections::HashSet, time::Duration};
+use std::{
+    collections::HashSet,
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc::UnboundedSender;
 use tokio_i3ipc::{event::Event, I3};
 
+/// Delivers the result of a background task back to the handler that spawned it, through the
+/// listener loop
+#[derive(Clone)]
+pub struct BackgroundSender {
+    handler: usize,
+    tx: UnboundedSender<(usize, String)>,
+}
+
+impl BackgroundSender {
+    pub fn new(handler: usize, tx: UnboundedSender<(usize, String)>) -> Self {
+        Self { handler, tx }
+    }
+
+    pub fn send(&self, msg: String) {
+        // The listener only goes away on exit
+        let _ = self.tx.send((self.handler, msg));
+    }
+}
+
 #[async_trait]
 pub trait OnEvent {
     // Need to use u32 since Subscribe doesn't impl Eq
     fn add_subscriptions(&self, subs: &mut HashSet<u32>);
+    /// Called after each (re)connection to i3, before any events are handled
+    async fn on_connect(&mut self, _i3: &mut I3) -> Option<String> {
+        None
+    }
     async fn handle_event(&mut self, e: &Event, i3: &mut I3) -> Option<String>;
+    /// Time at which `on_timeout` should next be called, if any
+    fn next_timeout(&self) -> Option<Instant> {
+        None
+    }
+    /// Called once the time returned by `next_timeout` has passed
+    async fn on_timeout(&mut self, _i3: &mut I3) -> Option<String> {
+        None
+    }
+    /// Called once at startup with the sender for results of background tasks
+    fn set_background(&mut self, _tx: BackgroundSender) {}
+    /// Called with each message sent through the sender given to `set_background`
+    async fn on_background(&mut self, _msg: &str, _i3: &mut I3) -> Option<String> {
+        None
+    }
 }
 
 pub trait OnTimer {
@@ -1,74 +1,176 @@
-use super::{keybinding::KeyBinding, parsable_duration::ParsableDuration, traits::OnEvent};
+use super::{
+    history::{HistOrder, History},
+    keybinding::KeyBinding,
+    node_utils::{get_focused_window, get_windows, get_workspaces, quote, scratchpad_shown},
+    notifier::Notifier,
+    own_commands::OwnCommands,
+    parsable_duration::ParsableDuration,
+    traits::{BackgroundSender, OnEvent},
+    MsgSender,
+};
 use async_trait::async_trait;
-use serde::Deserialize;
+use regex::Regex;
+use serde::{de::Error, Deserialize, Deserializer};
 use std::{
     collections::{vec_deque::VecDeque, HashMap, HashSet},
-    ops::{Add, AddAssign, Index},
+    fmt,
+    ops::{Add, AddAssign},
+    process::{exit, Stdio},
+    sync::Arc,
+    thread,
     time::{Duration, Instant},
 };
+use tokio::{io::AsyncWriteExt, process::Command};
 use tokio_i3ipc::{
     event as I3Event,
-    event::{Event, Subscribe, WorkspaceChange},
+    event::{BindingData, Event, Subscribe, WindowChange, WindowData, WorkspaceChange},
+    reply::{Node, Workspace},
     I3,
 };
 
 /// Config setting for history stack type
-#[derive(Clone, Copy, Deserialize)]
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize)]
 pub enum HistTypeConfig {
     Single,
     PerOutput,
+    /// Single stack whose traversal can go to workspaces visible on other outputs
+    CrossOutput,
 }
 
-/// History stack type (single or per-output)
-enum HistType {
-    Single(History),
-    PerOutput(HashMap<String, History>),
+/// What to do when traversal with `HistTypeConfig::CrossOutput` goes to a workspace on another
+/// output
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum CrossOutputMode {
+    /// Move focus to the workspace's output
+    Focus,
+    /// Move the workspace to the current output
+    Pull,
 }
-impl From<(HistTypeConfig, usize)> for HistType {
-    fn from(config: (HistTypeConfig, usize)) -> Self {
-        match config.0 {
-            HistTypeConfig::Single => Self::Single(History::with_capacity(config.1)),
-            HistTypeConfig::PerOutput => Self::PerOutput(HashMap::new()),
+
+/// Rules for workspaces that are left out of the history
+#[derive(Default, Deserialize)]
+pub struct ExcludeRules {
+    /// Patterns matching workspace names or numbers
+    #[serde(default, deserialize_with = "deserialize_regexes")]
+    pub workspaces: Vec<Regex>,
+    #[serde(default)]
+    pub outputs: Vec<String>,
+    /// Exclude workspaces that only contain floating windows
+    #[serde(default)]
+    pub floating_only: bool,
+}
+
+fn deserialize_regexes<'de, D>(deserializer: D) -> Result<Vec<Regex>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|pattern| {
+            Regex::new(pattern)
+                .map_err(|e| D::Error::custom(format!("Invalid regex '{}': {}", pattern, e)))
+        })
+        .collect()
+}
+
+impl ExcludeRules {
+    fn is_empty(&self) -> bool {
+        self.workspaces.is_empty() && self.outputs.is_empty() && !self.floating_only
+    }
+    /// Whether workspace `ws` should be left out of the history
+    fn matches(&self, ws: &Node) -> bool {
+        let name_matches = |s: &str| self.workspaces.iter().any(|re| re.is_match(s));
+        matches!(&ws.name, Some(name) if name_matches(name))
+            || matches!(ws.num, Some(num) if name_matches(&num.to_string()))
+            || matches!(&ws.output, Some(output) if self.outputs.contains(output))
+            || (self.floating_only && ws.nodes.is_empty() && !ws.floating_nodes.is_empty())
+    }
+    /// Numbers of all current workspaces that should be left out of the history
+    async fn get_excluded(&self, i3: &mut I3) -> HashSet<i32> {
+        if self.is_empty() {
+            return HashSet::new();
+        }
+        match i3.get_tree().await {
+            Ok(tree) => get_workspaces(&tree)
+                .into_iter()
+                .filter(|ws| self.matches(ws))
+                .filter_map(|ws| ws.num)
+                .collect(),
+            Err(_) => HashSet::new(),
         }
     }
 }
 
-/// History stack
-struct History {
-    hist: VecDeque<i32>,
-    hist_ptr: usize,
+/// Visit statistics of a workspace, used for frecency ordering
+struct WSStats {
+    visits: f64,
+    /// Minutes spent on the workspace
+    time_spent: f64,
+    updated: Instant,
 }
-impl History {
-    fn with_capacity(hist_sz: usize) -> Self {
+impl WSStats {
+    fn new(now: Instant) -> Self {
         Self {
-            hist: VecDeque::with_capacity(hist_sz),
-            hist_ptr: 0,
+            visits: 0.0,
+            time_spent: 0.0,
+            updated: now,
         }
     }
-    fn len(&self) -> usize {
-        self.hist.len()
+    fn decay_factor(&self, now: Instant, half_life: Duration) -> f64 {
+        0.5f64.powf((now - self.updated).as_secs_f64() / half_life.as_secs_f64())
     }
-    /// Reset the history pointer, reversing the order of history before it
-    /// NOTE: may change `hist.len()`
-    fn reset_ptr(&mut self) {
-        if self.hist_ptr > 0 {
-            // Reverse order of history that has been cycled back through,
-            // preventing double ups
-            if self.hist_ptr < self.hist.len() - 1 && self.hist[self.hist_ptr + 1] == self.hist[0] {
-                self.hist.pop_front();
-                self.hist_ptr -= 1;
-            }
-            for i in 0..=self.hist_ptr / 2 {
-                self.hist.swap(i, self.hist_ptr - i);
-            }
-            self.hist_ptr = 0;
+    /// Decay the statistics up to `now`
+    fn decay(&mut self, now: Instant, half_life: Duration) {
+        let factor = self.decay_factor(now, half_life);
+        self.visits *= factor;
+        self.time_spent *= factor;
+        self.updated = now;
+    }
+    /// One point per visit plus one point per minute spent on the workspace, halving every
+    /// `half_life`
+    fn score(&self, now: Instant, half_life: Duration) -> f64 {
+        (self.visits + self.time_spent) * self.decay_factor(now, half_life)
+    }
+}
+
+/// Entry of the history stack
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum HistEntry {
+    Workspace(i32),
+    /// Scratchpad window shown on top of a workspace, by container id
+    Scratchpad(usize),
+}
+impl HistEntry {
+    /// Number of the workspace, if this entry is one
+    fn ws_num(self) -> Option<i32> {
+        match self {
+            Self::Workspace(num) => Some(num),
+            Self::Scratchpad(_) => None,
+        }
+    }
+}
+impl fmt::Display for HistEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Workspace(num) => write!(f, "{}", num),
+            Self::Scratchpad(_) => write!(f, "scratchpad"),
         }
     }
 }
-impl Index<usize> for History {
-    type Output = i32;
-    fn index(&self, index: usize) -> &Self::Output {
-        &self.hist[index]
+
+/// History stack type (single or per-output)
+enum HistType {
+    Single(History<HistEntry>),
+    PerOutput(HashMap<String, History<HistEntry>>),
+}
+impl From<(HistTypeConfig, usize)> for HistType {
+    fn from(config: (HistTypeConfig, usize)) -> Self {
+        match config.0 {
+            HistTypeConfig::Single | HistTypeConfig::CrossOutput => {
+                Self::Single(History::with_capacity(config.1))
+            }
+            HistTypeConfig::PerOutput => Self::PerOutput(HashMap::new()),
+        }
     }
 }
 
@@ -76,29 +178,171 @@ impl Index<usize> for History {
 struct HistoryManager {
     hist: HistType,
     hist_sz: usize,
+    /// Time each entry was last visited
+    last_visit: HashMap<HistEntry, Instant>,
+    /// Stacks of disconnected outputs, restored when they reconnect
+    detached: HashMap<String, History<HistEntry>>,
+    order: HistOrder,
+    half_life: Duration,
+    stats: HashMap<i32, WSStats>,
+    /// Focused workspace, and when it was focused
+    focused: Option<(i32, Instant)>,
+    /// Stacks before recent destructive operations, by output ("" for a single stack)
+    undo: VecDeque<(String, History<HistEntry>)>,
+    /// Stacks before recent undos
+    redo: VecDeque<(String, History<HistEntry>)>,
+    undo_sz: usize,
+    /// Bookmarked workspace of each slot, by output ("" for a single stack)
+    bookmarks: HashMap<String, [Option<i32>; BOOKMARK_SLOTS]>,
 }
 impl From<(HistTypeConfig, usize)> for HistoryManager {
     fn from(config: (HistTypeConfig, usize)) -> Self {
         Self {
             hist_sz: config.1,
             hist: config.into(),
+            last_visit: HashMap::new(),
+            detached: HashMap::new(),
+            order: default_order(),
+            half_life: default_half_life(),
+            stats: HashMap::new(),
+            focused: None,
+            undo: VecDeque::new(),
+            redo: VecDeque::new(),
+            undo_sz: default_undo_sz(),
+            bookmarks: HashMap::new(),
         }
     }
 }
 impl HistoryManager {
-    fn get(&self, output: &String) -> Option<&History> {
+    fn with_order(mut self, order: HistOrder, half_life: Duration) -> Self {
+        self.order = order;
+        self.half_life = half_life;
+        self
+    }
+    fn with_undo_sz(mut self, undo_sz: usize) -> Self {
+        self.undo_sz = undo_sz;
+        self
+    }
+    /// Key for the stack of `output` in the undo journal and bookmarks
+    fn output_key(&self, output: &str) -> String {
+        match self.hist {
+            HistType::Single(_) => "".into(),
+            HistType::PerOutput(_) => output.into(),
+        }
+    }
+    /// Save the stack for `output` to the undo journal before it is modified
+    fn journal(&mut self, output: &String) {
+        if self.undo_sz == 0 {
+            return;
+        }
+        let key = self.output_key(output);
+        if let Some(hist) = self.get(output).cloned() {
+            self.redo.retain(|(o, _)| *o != key);
+            if self.undo.len() >= self.undo_sz {
+                self.undo.pop_front();
+            }
+            self.undo.push_back((key, hist));
+        }
+    }
+    /// Drop the journalled stacks for `output`, which a new visit has made stale
+    fn forget(&mut self, output: &str) {
+        let key = self.output_key(output);
+        self.undo.retain(|(o, _)| *o != key);
+        self.redo.retain(|(o, _)| *o != key);
+    }
+    /// Restore the stack for `output` from the undo journal (or the redo journal if `undo` is
+    /// false), saving the current stack to the other journal.
+    /// Returns the entry at the restored pointer if it differs from the current one.
+    fn undo(&mut self, output: &String, undo: bool) -> Option<HistEntry> {
+        let key = self.output_key(output);
+        let from = if undo { &mut self.undo } else { &mut self.redo };
+        let pos = from.iter().rposition(|(o, _)| *o == key)?;
+        let (_, saved) = from.remove(pos)?;
+        let hist = self.get_mut(output)?;
+        let old_ws = (hist.len() > 0).then(|| hist[hist.hist_ptr]);
+        let new_ws = (saved.len() > 0).then(|| saved[saved.hist_ptr]);
+        let old = std::mem::replace(hist, saved);
+        let to = if undo { &mut self.redo } else { &mut self.undo };
+        if to.len() >= self.undo_sz {
+            to.pop_front();
+        }
+        to.push_back((key, old));
+        new_ws.filter(|&ws| Some(ws) != old_ws)
+    }
+    /// Record that `ws_num` has been focused, accumulating the time spent on the previously
+    /// focused workspace
+    fn visit(&mut self, ws_num: i32) {
+        let now = Instant::now();
+        if let Some((prev, since)) = self.focused {
+            let stats = self.stats.entry(prev).or_insert_with(|| WSStats::new(now));
+            stats.decay(now, self.half_life);
+            stats.time_spent += (now - since).as_secs_f64() / 60.0;
+        }
+        let stats = self
+            .stats
+            .entry(ws_num)
+            .or_insert_with(|| WSStats::new(now));
+        stats.decay(now, self.half_life);
+        stats.visits += 1.0;
+        self.focused = Some((ws_num, now));
+    }
+    /// Current frecency score of each workspace, including time spent on the focused one
+    fn scores(&self) -> HashMap<HistEntry, f64> {
+        let now = Instant::now();
+        let mut scores: HashMap<HistEntry, f64> = self
+            .stats
+            .iter()
+            .map(|(&ws, stats)| (HistEntry::Workspace(ws), stats.score(now, self.half_life)))
+            .collect();
+        if let Some((ws, since)) = self.focused {
+            *scores.entry(HistEntry::Workspace(ws)).or_default() +=
+                (now - since).as_secs_f64() / 60.0;
+        }
+        scores
+    }
+    /// Reset the pointer of the stack for `output`
+    fn reset(&mut self, output: &String) {
+        let (order, scores) = (self.order, self.scores());
+        if let Some(hist) = self.get_mut(output) {
+            hist.reset(order, &scores);
+        }
+    }
+    /// Move the focused workspace of the stack for `output` to the top, without reordering the
+    /// workspaces above it
+    fn promote(&mut self, output: &String) {
+        let (order, scores) = (self.order, self.scores());
+        if let Some(hist) = self.get_mut(output) {
+            if order == HistOrder::Recency {
+                hist.promote();
+                hist.dedup();
+            } else {
+                hist.reset(order, &scores);
+            }
+        }
+    }
+    /// Reset the pointers of all stacks
+    fn reset_all(&mut self) {
+        let (order, scores) = (self.order, self.scores());
+        for hist in self.all_mut() {
+            hist.reset(order, &scores);
+        }
+    }
+    fn is_per_output(&self) -> bool {
+        matches!(self.hist, HistType::PerOutput(_))
+    }
+    fn get(&self, output: &String) -> Option<&History<HistEntry>> {
         match &self.hist {
             HistType::Single(hist) => Some(hist),
             HistType::PerOutput(hist) => hist.get(output),
         }
     }
-    fn get_mut(&mut self, output: &String) -> Option<&mut History> {
+    fn get_mut(&mut self, output: &String) -> Option<&mut History<HistEntry>> {
         match &mut self.hist {
             HistType::Single(hist) => Some(hist),
             HistType::PerOutput(hist) => hist.get_mut(output),
         }
     }
-    fn get_or_add_mut(&mut self, output: &String) -> &mut History {
+    fn get_or_add_mut(&mut self, output: &String) -> &mut History<HistEntry> {
         match &mut self.hist {
             HistType::Single(hist) => hist,
             HistType::PerOutput(hist) => {
@@ -109,16 +353,177 @@ impl HistoryManager {
             }
         }
     }
+    fn all_mut(&mut self) -> Vec<&mut History<HistEntry>> {
+        match &mut self.hist {
+            HistType::Single(hist) => vec![hist],
+            HistType::PerOutput(hist) => hist
+                .values_mut()
+                .chain(self.detached.values_mut())
+                .collect(),
+        }
+    }
+    /// Outputs that currently have a stack
+    fn outputs(&self) -> Vec<String> {
+        match &self.hist {
+            HistType::Single(_) => Vec::new(),
+            HistType::PerOutput(hist) => hist.keys().cloned().collect(),
+        }
+    }
+    /// Remove all occurrences of `entry` from every stack
+    fn remove_entry(&mut self, entry: HistEntry) {
+        for hist in self.all_mut() {
+            hist.remove(&entry);
+        }
+        self.last_visit.remove(&entry);
+    }
+    /// Remove all entries for `ws_num` from every stack
+    fn remove_ws(&mut self, ws_num: i32) {
+        self.remove_entry(HistEntry::Workspace(ws_num));
+        self.stats.remove(&ws_num);
+    }
+    /// Follow a workspace renumbering in every stack
+    fn rename_ws(&mut self, old_num: i32, new_num: i32) {
+        let unique = self.order.is_unique();
+        for hist in self.all_mut() {
+            hist.rename(
+                &HistEntry::Workspace(old_num),
+                HistEntry::Workspace(new_num),
+            );
+            if unique {
+                hist.unique();
+            }
+        }
+        if let Some(time) = self.last_visit.remove(&HistEntry::Workspace(old_num)) {
+            self.last_visit.insert(HistEntry::Workspace(new_num), time);
+        }
+        if let Some(stats) = self.stats.remove(&old_num) {
+            self.stats.insert(new_num, stats);
+        }
+        if let Some((ws, _)) = &mut self.focused {
+            if *ws == old_num {
+                *ws = new_num;
+            }
+        }
+        for ws in self.bookmarks.values_mut().flatten().flatten() {
+            if *ws == old_num {
+                *ws = new_num;
+            }
+        }
+    }
+    /// Pin `ws_num` to bookmark `slot` (0-based) of `output`
+    fn set_bookmark(&mut self, output: &str, slot: usize, ws_num: i32) {
+        let key = self.output_key(output);
+        if let Some(entry) = self.bookmarks.entry(key).or_default().get_mut(slot) {
+            *entry = Some(ws_num);
+        }
+    }
+    /// Workspace pinned to bookmark `slot` (0-based) of `output`
+    fn get_bookmark(&self, output: &str, slot: usize) -> Option<i32> {
+        *self.bookmarks.get(&self.output_key(output))?.get(slot)?
+    }
+    /// Set bookmarks of `output`, as (1-based slot, workspace) pairs
+    fn bookmarks(&self, output: &str) -> Vec<(usize, i32)> {
+        self.bookmarks
+            .get(&self.output_key(output))
+            .map(|slots| {
+                slots
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, ws)| ws.map(|ws| (i + 1, ws)))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+    /// Detach the stack of a disconnected output, merging its entries into the stack of
+    /// `receiver` (the output that its workspaces were moved to)
+    fn detach(&mut self, output: &String, receiver: Option<&String>) {
+        if let HistType::PerOutput(hist) = &mut self.hist {
+            if let Some(old) = hist.remove(output) {
+                if let Some(receiver) = receiver {
+                    let merged = hist
+                        .entry(receiver.clone())
+                        .or_insert_with(|| History::with_capacity(self.hist_sz));
+                    merged.merge(&old, &self.last_visit, self.hist_sz);
+                    if self.order.is_unique() {
+                        merged.unique();
+                    }
+                }
+                self.detached.insert(output.clone(), old);
+            }
+        }
+    }
+    /// Re-attach the stack of a reconnected output, removing its entries from other stacks.
+    /// Returns the workspaces in the restored stack.
+    fn attach(&mut self, output: &String) -> Vec<i32> {
+        let mut nums = Vec::new();
+        if let (HistType::PerOutput(hist), Some(restored)) =
+            (&mut self.hist, self.detached.remove(output))
+        {
+            for num in restored.hist.iter().filter_map(|entry| entry.ws_num()) {
+                if !nums.contains(&num) {
+                    nums.push(num);
+                }
+            }
+            for h in hist.values_mut() {
+                for &num in nums.iter() {
+                    h.remove(&HistEntry::Workspace(num));
+                }
+            }
+            hist.insert(output.clone(), restored);
+        }
+        nums
+    }
+}
+
+/// Time after which an open picker is closed
+const PICKER_TIMEOUT: Duration = Duration::from_secs(60);
+/// Number of bookmark slots per output
+const BOOKMARK_SLOTS: usize = 9;
+
+/// Traversal in progress through `binding_peek_prev`/`binding_peek_next`
+struct Peek {
+    output: String,
+    /// Pointer position before the traversal started
+    start_ptr: usize,
+}
+
+/// Picker shown by `binding_picker`/`binding_picker_move` that hasn't returned yet
+struct Pick {
+    /// Tells the result of this picker apart from those of pickers abandoned earlier
+    id: u64,
+    move_ws: bool,
+    /// Entries in the order they were listed in the picker
+    entries: Vec<HistEntry>,
 }
 
 /// Interface struct for workspace history stack
 pub struct WSHistory {
     hist: HistoryManager,
-    ignore_ctr: usize,
-    activity_timer: Instant,
+    own_cmds: OwnCommands,
+    /// When the pointer should next be reset, by output (or by "" if `timeout_per_output` is
+    /// false)
+    activity_timers: HashMap<String, Instant>,
     activity_timeout: Option<Duration>,
     cur_output: String,
+    /// Last known number of each workspace, by container id (used to follow renames)
+    ws_nums: HashMap<usize, i32>,
+    peek: Option<Peek>,
+    pick: Option<Pick>,
+    /// Id of the last picker shown
+    pick_id: u64,
+    background: Option<BackgroundSender>,
     pub skip_visible: bool,
+    /// Set if traversal can go to workspaces visible on other outputs
+    pub cross_output: Option<CrossOutputMode>,
+    pub purge_empty: bool,
+    /// Go back to the previous workspace when the focused one becomes empty
+    pub back_on_empty: bool,
+    /// Record shown scratchpad windows as entries of their own instead of workspace visits
+    pub track_scratchpad: bool,
+    /// Scratchpad window that has focus, by container id
+    scratchpad_focused: Option<usize>,
+    pub timeout_per_output: bool,
+    pub exclude: ExcludeRules,
     pub binding_prev: Option<KeyBinding>,
     pub binding_move_prev: Option<KeyBinding>,
     pub binding_next: Option<KeyBinding>,
@@ -128,9 +533,49 @@ pub struct WSHistory {
     pub binding_reset: Option<KeyBinding>,
     pub binding_to_head: Option<KeyBinding>,
     pub binding_move_to_head: Option<KeyBinding>,
+    pub binding_send_prev: Option<KeyBinding>,
+    pub binding_send_next: Option<KeyBinding>,
+    pub binding_send_to_head: Option<KeyBinding>,
+    pub binding_move_all_prev: Option<KeyBinding>,
+    pub binding_move_all_next: Option<KeyBinding>,
+    pub binding_move_all_to_head: Option<KeyBinding>,
     pub binding_rem_and_prev: Option<KeyBinding>,
     pub binding_rem_and_next: Option<KeyBinding>,
     pub binding_show_stack: Option<KeyBinding>,
+    pub binding_peek_prev: Option<KeyBinding>,
+    pub binding_peek_next: Option<KeyBinding>,
+    pub binding_peek_commit: Option<KeyBinding>,
+    /// `bindings_goto[i]` jumps to entry `i + 1` of the stack
+    pub bindings_goto: Vec<KeyBinding>,
+    pub bindings_move_to: Vec<KeyBinding>,
+    /// Menu command and arguments
+    pub picker_cmd: Option<Vec<String>>,
+    pub binding_picker: Option<KeyBinding>,
+    pub binding_picker_move: Option<KeyBinding>,
+    pub binding_undo: Option<KeyBinding>,
+    pub binding_redo: Option<KeyBinding>,
+    /// `bindings_pin[i]` pins the focused workspace to bookmark slot `i + 1`
+    pub bindings_pin: Vec<KeyBinding>,
+    pub bindings_goto_bookmark: Vec<KeyBinding>,
+    pub bindings_move_to_bookmark: Vec<KeyBinding>,
+    /// Pipe to publish the stack to
+    pub pipe: Option<Arc<dyn MsgSender + Send + Sync>>,
+    pub pipe_echo_fmt: String,
+    pub stack_entry_fmt: String,
+    pub stack_current_fmt: String,
+    pub stack_separator: String,
+    /// Format of the bookmarks message sent to `pipe` (bookmarks aren't sent if `None`)
+    pub bookmarks_echo_fmt: Option<String>,
+    pub bookmark_fmt: String,
+    /// Last stack rendering sent to `pipe`
+    published: Option<String>,
+    /// Last bookmarks rendering sent to `pipe`
+    published_bookmarks: Option<String>,
+    /// Show the stack for this long whenever it is traversed (disabled if `None`)
+    pub show_stack_on_traverse: Option<Duration>,
+    /// Id of the last stack notification, so that it is replaced by the next one
+    notification_id: Option<u32>,
+    pub notifier: Arc<Notifier>,
 }
 
 // serde default values
@@ -140,9 +585,33 @@ fn default_hist_sz() -> usize {
 fn default_skip_visible() -> bool {
     true
 }
+fn default_cross_output_mode() -> CrossOutputMode {
+    CrossOutputMode::Focus
+}
 fn default_hist_type() -> HistTypeConfig {
     HistTypeConfig::PerOutput
 }
+fn default_fmt() -> String {
+    "{}".into()
+}
+fn default_current_fmt() -> String {
+    "[{}]".into()
+}
+fn default_separator() -> String {
+    " ".into()
+}
+fn default_bookmark_fmt() -> String {
+    "{slot}:{ws}".into()
+}
+fn default_undo_sz() -> usize {
+    20
+}
+fn default_order() -> HistOrder {
+    HistOrder::Recency
+}
+fn default_half_life() -> Duration {
+    Duration::from_secs(3600)
+}
 
 /// Config input for `WSHistory`
 #[derive(Deserialize)]
@@ -151,9 +620,26 @@ pub struct WSHistoryConfig {
     pub hist_sz: usize,
     #[serde(default = "default_hist_type")]
     pub hist_type: HistTypeConfig,
+    #[serde(default = "default_order")]
+    pub order: HistOrder,
+    pub frecency_half_life: Option<ParsableDuration>,
+    #[serde(default = "default_undo_sz")]
+    pub undo_sz: usize,
+    #[serde(default = "default_cross_output_mode")]
+    pub cross_output_mode: CrossOutputMode,
     #[serde(default = "default_skip_visible")]
     pub skip_visible: bool,
+    #[serde(default)]
+    pub purge_empty: bool,
+    #[serde(default)]
+    pub back_on_empty: bool,
+    #[serde(default)]
+    pub track_scratchpad: bool,
+    #[serde(default)]
+    pub exclude: ExcludeRules,
     pub activity_timeout: Option<ParsableDuration>,
+    #[serde(default)]
+    pub timeout_per_output: bool,
     pub binding_prev: Option<KeyBinding>,
     pub binding_move_prev: Option<KeyBinding>,
     pub binding_next: Option<KeyBinding>,
@@ -163,9 +649,46 @@ pub struct WSHistoryConfig {
     pub binding_reset: Option<KeyBinding>,
     pub binding_to_head: Option<KeyBinding>,
     pub binding_move_to_head: Option<KeyBinding>,
+    pub binding_send_prev: Option<KeyBinding>,
+    pub binding_send_next: Option<KeyBinding>,
+    pub binding_send_to_head: Option<KeyBinding>,
+    pub binding_move_all_prev: Option<KeyBinding>,
+    pub binding_move_all_next: Option<KeyBinding>,
+    pub binding_move_all_to_head: Option<KeyBinding>,
     pub binding_rem_and_prev: Option<KeyBinding>,
     pub binding_rem_and_next: Option<KeyBinding>,
     pub binding_show_stack: Option<KeyBinding>,
+    pub binding_peek_prev: Option<KeyBinding>,
+    pub binding_peek_next: Option<KeyBinding>,
+    pub binding_peek_commit: Option<KeyBinding>,
+    #[serde(default)]
+    pub bindings_goto: Vec<KeyBinding>,
+    #[serde(default)]
+    pub bindings_move_to: Vec<KeyBinding>,
+    pub picker_cmd: Option<String>,
+    pub binding_picker: Option<KeyBinding>,
+    pub binding_picker_move: Option<KeyBinding>,
+    pub binding_undo: Option<KeyBinding>,
+    pub binding_redo: Option<KeyBinding>,
+    #[serde(default)]
+    pub bindings_pin: Vec<KeyBinding>,
+    #[serde(default)]
+    pub bindings_goto_bookmark: Vec<KeyBinding>,
+    #[serde(default)]
+    pub bindings_move_to_bookmark: Vec<KeyBinding>,
+    pub pipe_name: Option<String>,
+    #[serde(default = "default_fmt")]
+    pub pipe_echo_fmt: String,
+    #[serde(default = "default_fmt")]
+    pub stack_entry_fmt: String,
+    #[serde(default = "default_current_fmt")]
+    pub stack_current_fmt: String,
+    #[serde(default = "default_separator")]
+    pub stack_separator: String,
+    pub bookmarks_echo_fmt: Option<String>,
+    #[serde(default = "default_bookmark_fmt")]
+    pub bookmark_fmt: String,
+    pub show_stack_on_traverse: Option<ParsableDuration>,
 }
 
 impl Default for WSHistory {
@@ -173,39 +696,57 @@ impl Default for WSHistory {
         Self {
             hist: (default_hist_type(), default_hist_sz()).into(),
             skip_visible: default_skip_visible(),
-            ignore_ctr: 0,
+            cross_output: None,
+            purge_empty: false,
+            back_on_empty: false,
+            track_scratchpad: false,
+            scratchpad_focused: None,
+            exclude: ExcludeRules::default(),
+            own_cmds: OwnCommands::new("ws_history"),
             cur_output: "".to_string(),
-            activity_timer: Instant::now(),
-            activity_timeout: Some(Duration::from_secs(10).into()),
+            ws_nums: HashMap::new(),
+            peek: None,
+            pick: None,
+            pick_id: 0,
+            background: None,
+            activity_timers: HashMap::new(),
+            activity_timeout: Some(Duration::from_secs(10)),
+            timeout_per_output: false,
             binding_prev: Some(KeyBinding {
                 event_state_mask: vec!["Mod4".to_string()].into_iter().collect(),
                 symbol: Some("o".into()),
                 input_type: I3Event::BindType::Keyboard,
+                release: false,
             }),
             binding_move_prev: Some(KeyBinding {
                 event_state_mask: vec!["Mod4".into(), "shift".into()].into_iter().collect(),
                 symbol: Some("o".into()),
                 input_type: I3Event::BindType::Keyboard,
+                release: false,
             }),
             binding_next: Some(KeyBinding {
                 event_state_mask: vec!["Mod4".to_string()].into_iter().collect(),
                 symbol: Some("i".into()),
                 input_type: I3Event::BindType::Keyboard,
+                release: false,
             }),
             binding_move_next: Some(KeyBinding {
                 event_state_mask: vec!["Mod4".into(), "shift".into()].into_iter().collect(),
                 symbol: Some("i".into()),
                 input_type: I3Event::BindType::Keyboard,
+                release: false,
             }),
             binding_swap_prev: Some(KeyBinding {
                 event_state_mask: vec!["Mod4".into(), "ctrl".into()].into_iter().collect(),
                 symbol: Some("o".into()),
                 input_type: I3Event::BindType::Keyboard,
+                release: false,
             }),
             binding_swap_next: Some(KeyBinding {
                 event_state_mask: vec!["Mod4".into(), "ctrl".into()].into_iter().collect(),
                 symbol: Some("i".into()),
                 input_type: I3Event::BindType::Keyboard,
+                release: false,
             }),
             binding_reset: Some(KeyBinding {
                 event_state_mask: vec!["Mod4".into(), "ctrl".into(), "shift".into()]
@@ -213,6 +754,7 @@ impl Default for WSHistory {
                     .collect(),
                 symbol: Some("o".into()),
                 input_type: I3Event::BindType::Keyboard,
+                release: false,
             }),
             binding_to_head: Some(KeyBinding {
                 event_state_mask: vec!["Mod4".into(), "ctrl".into(), "shift".into()]
@@ -220,6 +762,7 @@ impl Default for WSHistory {
                     .collect(),
                 symbol: Some("i".into()),
                 input_type: I3Event::BindType::Keyboard,
+                release: false,
             }),
             binding_move_to_head: Some(KeyBinding {
                 event_state_mask: vec!["Mod4".into(), "Mod1".into(), "shift".into()]
@@ -227,35 +770,119 @@ impl Default for WSHistory {
                     .collect(),
                 symbol: Some("i".into()),
                 input_type: I3Event::BindType::Keyboard,
+                release: false,
             }),
+            binding_send_prev: None,
+            binding_send_next: None,
+            binding_send_to_head: None,
+            binding_move_all_prev: None,
+            binding_move_all_next: None,
+            binding_move_all_to_head: None,
             binding_rem_and_prev: Some(KeyBinding {
                 event_state_mask: vec!["Mod4".into(), "Mod1".into()].into_iter().collect(),
                 symbol: Some("o".into()),
                 input_type: I3Event::BindType::Keyboard,
+                release: false,
             }),
             binding_rem_and_next: Some(KeyBinding {
                 event_state_mask: vec!["Mod4".into(), "Mod1".into()].into_iter().collect(),
                 symbol: Some("i".into()),
                 input_type: I3Event::BindType::Keyboard,
+                release: false,
             }),
             binding_show_stack: Some(KeyBinding {
                 event_state_mask: vec!["Mod4".into(), "ctrl".into()].into_iter().collect(),
                 symbol: Some("s".into()),
                 input_type: I3Event::BindType::Keyboard,
+                release: false,
             }),
+            binding_peek_prev: None,
+            binding_peek_next: None,
+            binding_peek_commit: None,
+            bindings_goto: Vec::new(),
+            bindings_move_to: Vec::new(),
+            picker_cmd: None,
+            binding_picker: None,
+            binding_picker_move: None,
+            binding_undo: None,
+            binding_redo: None,
+            bindings_pin: Vec::new(),
+            bindings_goto_bookmark: Vec::new(),
+            bindings_move_to_bookmark: Vec::new(),
+            pipe: None,
+            pipe_echo_fmt: default_fmt(),
+            stack_entry_fmt: default_fmt(),
+            stack_current_fmt: default_current_fmt(),
+            stack_separator: default_separator(),
+            bookmarks_echo_fmt: None,
+            bookmark_fmt: default_bookmark_fmt(),
+            published: None,
+            published_bookmarks: None,
+            show_stack_on_traverse: None,
+            notification_id: None,
+            notifier: Arc::new(Notifier::default()),
         }
     }
 }
 
-impl From<WSHistoryConfig> for WSHistory {
-    fn from(config: WSHistoryConfig) -> Self {
+impl
+    From<(
+        WSHistoryConfig,
+        Option<&HashMap<String, Arc<dyn MsgSender + Send + Sync>>>,
+        Arc<Notifier>,
+    )> for WSHistory
+{
+    fn from(
+        (config, pipes, notifier): (
+            WSHistoryConfig,
+            Option<&HashMap<String, Arc<dyn MsgSender + Send + Sync>>>,
+            Arc<Notifier>,
+        ),
+    ) -> Self {
+        for (name, bindings) in [
+            ("bindings_pin", &config.bindings_pin),
+            ("bindings_goto_bookmark", &config.bindings_goto_bookmark),
+            (
+                "bindings_move_to_bookmark",
+                &config.bindings_move_to_bookmark,
+            ),
+        ] {
+            if bindings.len() > BOOKMARK_SLOTS {
+                eprintln!(
+                    "ERROR: {} has more than {} bookmark slots",
+                    name, BOOKMARK_SLOTS
+                );
+                exit(11);
+            }
+        }
         Self {
-            hist: (config.hist_type, config.hist_sz).into(),
-            ignore_ctr: 0,
+            hist: HistoryManager::from((config.hist_type, config.hist_sz))
+                .with_order(
+                    config.order,
+                    config
+                        .frecency_half_life
+                        .map(|d| d.into())
+                        .unwrap_or_else(default_half_life),
+                )
+                .with_undo_sz(config.undo_sz),
+            own_cmds: OwnCommands::new("ws_history"),
             skip_visible: config.skip_visible,
-            activity_timer: Instant::now(),
+            cross_output: Some(config.cross_output_mode)
+                .filter(|_| config.hist_type == HistTypeConfig::CrossOutput),
+            purge_empty: config.purge_empty,
+            back_on_empty: config.back_on_empty,
+            track_scratchpad: config.track_scratchpad,
+            scratchpad_focused: None,
+            exclude: config.exclude,
+            activity_timers: HashMap::new(),
             activity_timeout: config.activity_timeout.map(|d| d.into()),
+            timeout_per_output: config.timeout_per_output,
             cur_output: "".to_string(),
+            ws_nums: HashMap::new(),
+            peek: None,
+            pick: None,
+            pick_id: 0,
+            background: None,
             binding_prev: config.binding_prev,
             binding_move_prev: config.binding_move_prev,
             binding_next: config.binding_next,
@@ -265,132 +892,653 @@ impl From<WSHistoryConfig> for WSHistory {
             binding_reset: config.binding_reset,
             binding_to_head: config.binding_to_head,
             binding_move_to_head: config.binding_move_to_head,
+            binding_send_prev: config.binding_send_prev,
+            binding_send_next: config.binding_send_next,
+            binding_send_to_head: config.binding_send_to_head,
+            binding_move_all_prev: config.binding_move_all_prev,
+            binding_move_all_next: config.binding_move_all_next,
+            binding_move_all_to_head: config.binding_move_all_to_head,
             binding_rem_and_prev: config.binding_rem_and_prev,
             binding_rem_and_next: config.binding_rem_and_next,
             binding_show_stack: config.binding_show_stack,
+            binding_peek_prev: config.binding_peek_prev,
+            binding_peek_next: config.binding_peek_next,
+            binding_peek_commit: config.binding_peek_commit,
+            bindings_goto: config.bindings_goto,
+            bindings_move_to: config.bindings_move_to,
+            picker_cmd: config.picker_cmd.map(|cmd| match shellwords::split(&cmd) {
+                Ok(args) if !args.is_empty() => args,
+                _ => {
+                    eprintln!("ERROR: invalid picker_cmd: {}", cmd);
+                    exit(10);
+                }
+            }),
+            binding_picker: config.binding_picker,
+            binding_picker_move: config.binding_picker_move,
+            binding_undo: config.binding_undo,
+            binding_redo: config.binding_redo,
+            bindings_pin: config.bindings_pin,
+            bindings_goto_bookmark: config.bindings_goto_bookmark,
+            bindings_move_to_bookmark: config.bindings_move_to_bookmark,
+            pipe: config.pipe_name.map(|name| {
+                pipes
+                    .and_then(|p| p.get(&name))
+                    .unwrap_or_else(|| {
+                        eprintln!("ERROR: pipe '{}' not found in config file", name);
+                        exit(6);
+                    })
+                    .clone()
+            }),
+            pipe_echo_fmt: config.pipe_echo_fmt,
+            stack_entry_fmt: config.stack_entry_fmt,
+            stack_current_fmt: config.stack_current_fmt,
+            stack_separator: config.stack_separator,
+            bookmarks_echo_fmt: config.bookmarks_echo_fmt,
+            bookmark_fmt: config.bookmark_fmt,
+            published: None,
+            published_bookmarks: None,
+            show_stack_on_traverse: config.show_stack_on_traverse.map(|d| d.into()),
+            notification_id: None,
+            notifier,
         }
     }
 }
 
 impl WSHistory {
+    /// Whether traversal skips over workspace `ws`, according to the `hist_type` and
+    /// `skip_visible` settings
+    fn skips(&self, ws: &Workspace) -> bool {
+        let other_output = ws.output != self.cur_output;
+        (self.hist.is_per_output() && other_output)
+            || (self.skip_visible && ws.visible && !(self.cross_output.is_some() && other_output))
+    }
+
+    /// Command to go to `entry`. Workspaces are pulled to the current output if using
+    /// `cross_output_mode = "Pull"`, and scratchpad windows are shown on the current workspace.
+    fn focus_cmd(&self, entry: HistEntry) -> String {
+        let ws_num = match entry {
+            HistEntry::Workspace(num) => num,
+            HistEntry::Scratchpad(id) => {
+                // Hide it first so that it is shown even if it is already visible
+                return format!(
+                    "[con_id={0}] move scratchpad; [con_id={0}] scratchpad show",
+                    id
+                );
+            }
+        };
+        match self.cross_output {
+            Some(CrossOutputMode::Pull) => format!(
+                "workspace number {}; move workspace to output {}",
+                ws_num,
+                quote(&self.cur_output)
+            ),
+            Some(CrossOutputMode::Focus) | None => format!("workspace number {}", ws_num),
+        }
+    }
+
+    /// Command to move every top-level container of workspace `src` to workspace `ws_num` and
+    /// follow them. The containers are moved by id so that their layout is kept.
+    fn move_all_cmd(&self, src: &Node, ws_num: i32) -> String {
+        src.nodes
+            .iter()
+            .chain(src.floating_nodes.iter())
+            .map(|n| {
+                format!(
+                    "[con_id={}] move container to workspace number {}",
+                    n.id, ws_num
+                )
+            })
+            .chain(Some(self.focus_cmd(HistEntry::Workspace(ws_num))))
+            .collect::<Vec<String>>()
+            .join("; ")
+    }
+
+    /// Move every container of the focused workspace to the next/previous workspace in the
+    /// stack, or to the top workspace if `dir` is `None`, returning the command to run.
+    /// The emptied workspace is destroyed by i3, so all of its entries leave the stacks.
+    async fn move_all(&mut self, dir: Option<WSDirection>, i3: &mut I3) -> Option<String> {
+        let src = i3
+            .get_workspaces()
+            .await
+            .ok()?
+            .into_iter()
+            .find(|ws| ws.focused)?
+            .num;
+        let tree = i3.get_tree().await.ok()?;
+        let ws = get_workspaces(&tree)
+            .into_iter()
+            .find(|ws| ws.num == Some(src))?;
+        let dest = match dir {
+            Some(dir) => self.rem_ws(dir, true, i3).await?.ws_num()?,
+            None => self.rem_to_head(i3).await?,
+        };
+        let cmd = self.move_all_cmd(ws, dest);
+        self.hist.remove_ws(src);
+        Some(cmd)
+    }
+
+    /// Command to move the focused container to workspace `ws_num` and follow it
+    fn move_cmd(&self, ws_num: i32) -> String {
+        format!(
+            "move container to workspace number {}; {}",
+            ws_num,
+            self.focus_cmd(HistEntry::Workspace(ws_num))
+        )
+    }
+
     /// Get the next or previous workspace from the history stack, relative to `cur_ws`
     /// Returns the index in the stack of that workspace if there is one available in that
     /// direction. Otherwise returns `None`.
-    async fn get_ws(&self, cur_ws: usize, dir: WSDirection, i3: &mut I3) -> Option<usize> {
-        let per_output = match self.hist.hist {
-            HistType::PerOutput(_) => true,
-            _ => false,
-        };
+    /// Scratchpad entries are skipped if `ws_only` is set.
+    async fn get_ws(
+        &self,
+        cur_ws: usize,
+        dir: WSDirection,
+        ws_only: bool,
+        i3: &mut I3,
+    ) -> Option<usize> {
+        let per_output = self.hist.is_per_output();
         let hist = self.hist.get(&self.cur_output)?;
         let limit = hist.len() - 1;
         let check_range = |hist_ptr| match dir {
             WSDirection::PREV => hist_ptr < limit,
             WSDirection::NEXT => hist_ptr > 0,
         };
-        if check_range(cur_ws) {
-            if self.skip_visible || per_output {
-                if let Ok(workspaces) = i3.get_workspaces().await {
-                    let mut dest_ws = cur_ws + dir;
-                    loop {
-                        if matches!(workspaces.iter().find(|&w| w.num == hist[dest_ws]), Some(ws)
-                            if (self.skip_visible && ws.visible) || (per_output && ws.output != self.cur_output))
-                        {
-                            dest_ws += dir;
-                        } else {
-                            return Some(dest_ws);
-                        }
-                        if !check_range(dest_ws) {
-                            break;
-                        }
-                    }
-                    None
-                } else {
-                    Some(cur_ws + dir)
+        if !check_range(cur_ws) {
+            return None;
+        }
+        let excluded = self.exclude.get_excluded(i3).await;
+        let workspaces = if self.skip_visible || per_output {
+            i3.get_workspaces().await.ok()
+        } else {
+            None
+        };
+        let mut dest_ws = cur_ws + dir;
+        loop {
+            let skip = match hist[dest_ws] {
+                HistEntry::Workspace(num) => {
+                    excluded.contains(&num)
+                        || matches!(workspaces.iter().flatten().find(|&w| w.num == num), Some(ws)
+                            if self.skips(ws))
                 }
-            } else {
-                Some(cur_ws + dir)
+                HistEntry::Scratchpad(_) => ws_only,
+            };
+            if !skip {
+                return Some(dest_ws);
             }
+            if !check_range(dest_ws) {
+                return None;
+            }
+            dest_ws += dir;
+        }
+    }
+
+    /// Indices of the entries in the current stack that can be jumped to, from the top of the
+    /// stack down. Entries that would be skipped by traversal are left out, except for the one at
+    /// the pointer, and repeats of the same workspace are only listed once.
+    async fn get_entries(&self, i3: &mut I3) -> Option<Vec<usize>> {
+        let per_output = self.hist.is_per_output();
+        let excluded = self.exclude.get_excluded(i3).await;
+        let workspaces = if self.skip_visible || per_output {
+            i3.get_workspaces().await.ok()
         } else {
             None
+        };
+        let hist = self.hist.get(&self.cur_output)?;
+        let mut out: Vec<usize> = Vec::with_capacity(hist.len());
+        for (id, ws) in hist.hist.iter().enumerate() {
+            let skip = matches!(ws, &HistEntry::Workspace(num) if excluded.contains(&num)
+                || matches!(workspaces.iter().flatten().find(|&w| w.num == num), Some(w)
+                    if self.skips(w)));
+            if skip && id != hist.hist_ptr {
+                continue;
+            }
+            match out.last_mut() {
+                Some(last) if hist[*last] == *ws => {
+                    if id == hist.hist_ptr {
+                        *last = id;
+                    }
+                }
+                _ => out.push(id),
+            }
+        }
+        Some(out)
+    }
+
+    /// Jump to entry `n` (1-based) of the entries returned by `get_entries`, unless `ws_only`
+    /// is set and it is a scratchpad entry.
+    /// Returns `Some(new_entry)` if stack pointer changed, `None` otherwise.
+    async fn goto_nth(&mut self, n: usize, ws_only: bool, i3: &mut I3) -> Option<HistEntry> {
+        self.check_timeout();
+        let entries = self.get_entries(i3).await?;
+        let id = *entries.get(n.checked_sub(1)?)?;
+        let hist = self.hist.get_mut(&self.cur_output)?;
+        if id == hist.hist_ptr || (ws_only && hist[id].ws_num().is_none()) {
+            return None;
+        }
+        hist.hist_ptr = id;
+        Some(hist[id])
+    }
+
+    /// Render the entries of the current stack for the status bar
+    async fn render(&self, i3: &mut I3) -> Option<String> {
+        let entries = self.get_entries(i3).await?;
+        let hist = self.hist.get(&self.cur_output)?;
+        Some(
+            entries
+                .iter()
+                .map(|&id| {
+                    let fmt = if id == hist.hist_ptr {
+                        &self.stack_current_fmt
+                    } else {
+                        &self.stack_entry_fmt
+                    };
+                    fmt.replace("{}", &hist[id].to_string())
+                })
+                .collect::<Vec<String>>()
+                .join(&self.stack_separator),
+        )
+    }
+
+    /// Render the bookmarks of the current output for the status bar
+    fn render_bookmarks(&self) -> String {
+        self.hist
+            .bookmarks(&self.cur_output)
+            .iter()
+            .map(|(slot, ws)| {
+                self.bookmark_fmt
+                    .replace("{slot}", &slot.to_string())
+                    .replace("{ws}", &ws.to_string())
+            })
+            .collect::<Vec<String>>()
+            .join(&self.stack_separator)
+    }
+
+    /// Send the current stack (and bookmarks if `bookmarks_echo_fmt` is set) to `pipe` if they
+    /// have changed since they were last sent
+    async fn publish(&mut self, i3: &mut I3) {
+        let pipe = match &self.pipe {
+            Some(pipe) => pipe.clone(),
+            None => return,
+        };
+        let mut msgs = Vec::new();
+        let rendered = self.render(i3).await.unwrap_or_default();
+        if self.published.as_ref() != Some(&rendered) {
+            msgs.push(self.pipe_echo_fmt.replace("{}", &rendered));
+            self.published = Some(rendered);
+        }
+        if let Some(fmt) = &self.bookmarks_echo_fmt {
+            let rendered = self.render_bookmarks();
+            if self.published_bookmarks.as_ref() != Some(&rendered) {
+                msgs.push(fmt.replace("{}", &rendered));
+                self.published_bookmarks = Some(rendered);
+            }
+        }
+        if !msgs.is_empty() {
+            thread::spawn(move || {
+                for msg in msgs {
+                    pipe.send(msg.as_str());
+                }
+            });
+        }
+    }
+
+    /// Handle the bookmark bindings, running the command to jump to a bookmark (if any).
+    /// Returns false if `key` isn't one of them.
+    /// Jumping to a bookmark is recorded in the history like any other workspace change.
+    async fn handle_bookmark(&mut self, key: &BindingData, i3: &mut I3) -> bool {
+        let pin = self.bindings_pin.iter().position(|kb| kb == key);
+        let goto = self.bindings_goto_bookmark.iter().position(|kb| kb == key);
+        let move_to = self
+            .bindings_move_to_bookmark
+            .iter()
+            .position(|kb| kb == key);
+        let slot = match pin.or(goto).or(move_to) {
+            Some(slot) => slot,
+            None => return false,
+        };
+        self.commit_peek();
+        let focused = match i3.get_workspaces().await {
+            Ok(workspaces) => workspaces.into_iter().find(|ws| ws.focused),
+            Err(_) => None,
+        };
+        if pin.is_some() {
+            if let Some(ws) = focused {
+                self.hist.set_bookmark(&self.cur_output, slot, ws.num);
+            }
+            return true;
+        }
+        let dest_ws = match self
+            .hist
+            .get_bookmark(&self.cur_output, slot)
+            .filter(|&num| !matches!(&focused, Some(ws) if ws.num == num))
+        {
+            Some(ws) => ws,
+            None => return true,
+        };
+        self.check_timeout();
+        let cmd = match goto {
+            Some(_) => self.focus_cmd(HistEntry::Workspace(dest_ws)),
+            None => self.move_cmd(dest_ws),
+        };
+        self.own_cmds.run(&cmd, i3).await;
+        // Our own commands aren't recorded, so record the workspace change here
+        let excluded = self.exclude.get_excluded(i3).await;
+        let new_focused = match i3.get_workspaces().await {
+            Ok(workspaces) => workspaces.into_iter().find(|ws| ws.focused),
+            Err(_) => None,
+        };
+        for ws in [focused, new_focused].into_iter().flatten() {
+            if !excluded.contains(&ws.num) {
+                self.add_entry(HistEntry::Workspace(ws.num), &ws.output);
+            }
+        }
+        true
+    }
+
+    /// Show the entries of the current stack in `picker_cmd`, with the name of each workspace
+    /// and the title of its focused window (or the title of the window of scratchpad entries).
+    /// The picker runs in the background, and the selected line is handled by `on_background`.
+    async fn pick(&mut self, move_ws: bool, i3: &mut I3) -> Option<()> {
+        if self.pick.is_some() {
+            return None;
+        }
+        let cmd = self.picker_cmd.clone()?;
+        let tx = self.background.clone()?;
+        let entries = self.get_entries(i3).await?;
+        let tree = i3.get_tree().await.ok()?;
+        let hist = self.hist.get(&self.cur_output)?;
+        let entries: Vec<HistEntry> = entries.iter().map(|&id| hist[id]).collect();
+        let input = entries
+            .iter()
+            .enumerate()
+            .map(|(n, &entry)| {
+                let (name, window) = describe_entry(entry, &tree);
+                match window.and_then(|w| w.name.as_ref()) {
+                    Some(title) => format!("{}: {} - {}", n + 1, name, title),
+                    None => format!("{}: {}", n + 1, name),
+                }
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        self.pick_id += 1;
+        let id = self.pick_id;
+        self.pick = Some(Pick {
+            id,
+            move_ws,
+            entries,
+        });
+        tokio::spawn(async move {
+            let selected = run_picker(&cmd, &input).await.unwrap_or_default();
+            tx.send(format!("{}:{}", id, selected));
+        });
+        Some(())
+    }
+
+    /// Go to the entry selected in the picker (`msg` is the picker's id and the selected line),
+    /// wherever it is in the stack by now.
+    /// Returns the i3 command to run (if any)
+    async fn picked(&mut self, msg: &str, i3: &mut I3) -> Option<String> {
+        let (id, selected) = msg.split_once(':')?;
+        if !matches!(&self.pick, Some(pick) if pick.id.to_string() == id) {
+            return None;
+        }
+        let pick = self.pick.take()?;
+        let n: usize = selected.split(':').next()?.trim().parse().ok()?;
+        let entry = *pick.entries.get(n.checked_sub(1)?)?;
+        let entries = self.get_entries(i3).await?;
+        let hist = self.hist.get(&self.cur_output)?;
+        let pos = entries.iter().position(|&id| hist[id] == entry)?;
+        let new_ws = self.goto_nth(pos + 1, pick.move_ws, i3).await?;
+        if pick.move_ws {
+            new_ws.ws_num().map(|ws| self.move_cmd(ws))
+        } else {
+            Some(self.focus_cmd(new_ws))
         }
     }
 
-    /// Go to the next or previous workspace in the stack.
-    /// Returns `None` if workspace didn't change, or `Some(new_ws)` if it did
-    async fn goto_ws(&mut self, dir: WSDirection, i3: &mut I3) -> Option<i32> {
+    /// Go to the next or previous entry in the stack, skipping scratchpad entries if `ws_only`
+    /// is set.
+    /// Returns `None` if the entry didn't change, or `Some(new_entry)` if it did
+    async fn goto_ws(&mut self, dir: WSDirection, ws_only: bool, i3: &mut I3) -> Option<HistEntry> {
         self.check_timeout();
         let hist = self.hist.get(&self.cur_output)?;
-        let new_ws = self.get_ws(hist.hist_ptr, dir, i3).await?;
+        let new_ws = self.get_ws(hist.hist_ptr, dir, ws_only, i3).await?;
         let hist = self.hist.get_mut(&self.cur_output)?;
         hist.hist_ptr = new_ws;
         Some(hist[hist.hist_ptr])
     }
 
-    /// Jump to the top workspace in the stack.
-    /// Returns `Some(new_ws)` if stack pointer changed, `None` otherwise.
-    async fn goto_head(&mut self, i3: &mut I3) -> Option<i32> {
+    /// Preview the next or previous workspace in the stack, starting a peek traversal if there
+    /// isn't one in progress. The stack isn't reordered until the traversal is committed.
+    /// Returns `Some(new_entry)` if the entry changed.
+    async fn peek_ws(&mut self, dir: WSDirection, i3: &mut I3) -> Option<HistEntry> {
+        if self.peek.is_none() {
+            self.check_timeout();
+            self.peek = Some(Peek {
+                output: self.cur_output.clone(),
+                start_ptr: self.hist.get(&self.cur_output)?.hist_ptr,
+            });
+        }
+        self.goto_ws(dir, false, i3).await
+    }
+
+    /// Finish a peek traversal, moving the workspace it ended on to the top of the stack
+    fn commit_peek(&mut self) {
+        if let Some(peek) = self.peek.take() {
+            self.hist.promote(&peek.output);
+        }
+    }
+
+    /// Cancel a peek traversal, returning the pointer to where it started
+    fn abort_peek(&mut self) {
+        if let Some(peek) = self.peek.take() {
+            if let Some(hist) = self.hist.get_mut(&peek.output) {
+                hist.hist_ptr = peek.start_ptr.min(hist.len().saturating_sub(1));
+            }
+        }
+    }
+
+    /// Index of the top entry of the stack that isn't skipped by traversal, skipping scratchpad
+    /// entries if `ws_only` is set
+    async fn get_head(&self, ws_only: bool, i3: &mut I3) -> Option<usize> {
+        let per_output = self.hist.is_per_output();
+        let mut skipped = self.exclude.get_excluded(i3).await;
+        if self.skip_visible || per_output {
+            if let Ok(workspaces) = i3.get_workspaces().await {
+                skipped.extend(
+                    workspaces
+                        .iter()
+                        .filter(|ws| self.skips(ws) || (per_output && ws.visible))
+                        .map(|ws| ws.num),
+                );
+            }
+        }
+        let hist = self.hist.get(&self.cur_output)?;
+        let limit = hist.len().saturating_sub(1);
+        Some(
+            (0..limit)
+                .find(|&id| match hist[id] {
+                    HistEntry::Workspace(num) => !skipped.contains(&num),
+                    HistEntry::Scratchpad(_) => !ws_only,
+                })
+                .unwrap_or(0),
+        )
+    }
+
+    /// Jump to the top entry in the stack, skipping scratchpad entries if `ws_only` is set.
+    /// Returns `Some(new_entry)` if stack pointer changed, `None` otherwise.
+    async fn goto_head(&mut self, ws_only: bool, i3: &mut I3) -> Option<HistEntry> {
         self.check_timeout();
-        let per_output = match self.hist.hist {
-            HistType::PerOutput(_) => true,
-            _ => false,
-        };
+        if self.hist.get(&self.cur_output)?.hist_ptr == 0 {
+            return None;
+        }
+        let head = self.get_head(ws_only, i3).await?;
         let hist = self.hist.get_mut(&self.cur_output)?;
-        if hist.hist_ptr == 0 {
+        if head == hist.hist_ptr {
             return None;
         }
-        let old_ptr = hist.hist_ptr;
-        hist.hist_ptr = 0;
-        if self.skip_visible || per_output {
-            if let Ok(workspaces) = i3.get_workspaces().await {
-                let limit = hist.len() - 1;
-                let mut dest_ws = hist.hist_ptr;
-                while dest_ws < limit {
-                    if matches!(workspaces.iter().find(|&w| w.num == hist[dest_ws]), Some(ws)
-                        if (per_output && (ws.output != self.cur_output || ws.visible))
-                        || (!per_output && self.skip_visible && ws.visible))
-                    {
-                        dest_ws += 1;
-                    } else {
-                        hist.hist_ptr = dest_ws;
-                        break;
-                    }
-                }
+        hist.hist_ptr = head;
+        Some(hist[head])
+    }
+
+    /// Workspace that traversal in direction `dir` would go to, or the top workspace in the
+    /// stack if `dir` is `None`, without moving the pointer
+    async fn get_target(&mut self, dir: Option<WSDirection>, i3: &mut I3) -> Option<i32> {
+        self.check_timeout();
+        let cur_ptr = self.hist.get(&self.cur_output)?.hist_ptr;
+        let id = match dir {
+            Some(dir) => self.get_ws(cur_ptr, dir, true, i3).await?,
+            None if cur_ptr > 0 => self.get_head(true, i3).await?,
+            None => return None,
+        };
+        if id == cur_ptr {
+            return None;
+        }
+        self.hist.get(&self.cur_output)?[id].ws_num()
+    }
+
+    /// Jump to the top workspace in the stack and remove the current one from the stack
+    /// Returns the workspace number of the new workspace if it changed
+    async fn rem_to_head(&mut self, i3: &mut I3) -> Option<i32> {
+        self.check_timeout();
+        let cur_ptr = self.hist.get(&self.cur_output)?.hist_ptr;
+        if cur_ptr == 0 {
+            return None;
+        }
+        let head = self.get_head(true, i3).await?;
+        if head == cur_ptr {
+            return None;
+        }
+        self.hist.journal(&self.cur_output);
+        let hist = self.hist.get_mut(&self.cur_output)?;
+        hist.hist.remove(cur_ptr);
+        hist.hist_ptr = if cur_ptr < head { head - 1 } else { head };
+        hist[hist.hist_ptr].ws_num()
+    }
+
+    /// Add `entry` to the history, resetting the history pointer
+    fn add_entry(&mut self, entry: HistEntry, output: &String) {
+        self.hist.forget(output);
+        self.hist.last_visit.insert(entry, Instant::now());
+        let hist_sz = self.hist.hist_sz;
+        let (order, scores) = (self.hist.order, self.hist.scores());
+        self.hist
+            .get_or_add_mut(output)
+            .push(entry, hist_sz, order, &scores);
+    }
+
+    /// Migrate the entries for a workspace that was moved to `output` from the stacks of other
+    /// outputs. If the workspace isn't already in its new stack, it goes on top if it is focused
+    /// or at the bottom otherwise.
+    async fn move_ws(&mut self, ws_num: i32, output: &String, i3: &mut I3) {
+        if let HistType::PerOutput(hist) = &mut self.hist.hist {
+            for (_, h) in hist.iter_mut().filter(|(o, _)| *o != output) {
+                h.remove(&HistEntry::Workspace(ws_num));
             }
+        } else {
+            return;
         }
-        if hist.hist_ptr != old_ptr {
-            Some(hist[hist.hist_ptr])
+        let focused = match i3.get_workspaces().await {
+            Ok(workspaces) => workspaces.into_iter().find(|ws| ws.focused),
+            Err(_) => None,
+        };
+        if let Some(focused) = &focused {
+            self.cur_output = focused.output.clone();
+        }
+        let entry = HistEntry::Workspace(ws_num);
+        if matches!(self.hist.get(output), Some(hist) if hist.hist.contains(&entry)) {
+            return;
+        }
+        if matches!(focused, Some(ws) if ws.num == ws_num) {
+            self.add_entry(entry, output);
         } else {
-            None
+            let hist_sz = self.hist.hist_sz;
+            let hist = self.hist.get_or_add_mut(output);
+            if hist.len() < hist_sz {
+                hist.hist.push_back(entry);
+            }
         }
     }
 
-    /// Add `ws_num` to the history, resetting the history pointer
-    fn add_ws(&mut self, ws_num: i32, output: &String) {
-        let hist_sz = self.hist.hist_sz;
-        let hist = self.hist.get_or_add_mut(output);
-        // Add `ws_num` to history if it won't create a duplicate
-        if hist.len() == 0 || hist[hist.hist_ptr] != ws_num {
-            hist.reset_ptr();
-            // Prevent duplicate sequences of 2
-            if hist.len() > 2 && hist[0] == hist[2] && ws_num == hist[1] {
-                hist.hist.pop_front();
-            } else {
-                // Add new ws, forgetting oldest if at max length
-                hist.hist.truncate(hist_sz);
-                hist.hist.push_front(ws_num);
+    /// Merge the stacks of disconnected outputs into the stack of the output that received their
+    /// workspaces, and restore them when the output reconnects.
+    /// Returns the commands to move workspaces back to reconnected outputs.
+    async fn update_outputs(&mut self, i3: &mut I3) -> Option<String> {
+        if !self.hist.is_per_output() {
+            return None;
+        }
+        let outputs = i3.get_outputs().await.ok()?;
+        let workspaces = i3.get_workspaces().await.ok()?;
+        let active: Vec<&String> = outputs
+            .iter()
+            .filter(|o| o.active)
+            .map(|o| &o.name)
+            .collect();
+
+        for output in self.hist.outputs() {
+            if active.contains(&&output) {
+                continue;
             }
+            let receiver = self
+                .hist
+                .get(&output)
+                .and_then(|hist| {
+                    hist.hist.iter().filter_map(|e| e.ws_num()).find_map(|num| {
+                        workspaces
+                            .iter()
+                            .find(|ws| ws.num == num && ws.output != output)
+                    })
+                })
+                .map(|ws| &ws.output)
+                .or_else(|| active.first().copied());
+            self.hist.detach(&output, receiver);
         }
+
+        let reconnected: Vec<String> = self
+            .hist
+            .detached
+            .keys()
+            .filter(|o| active.contains(o))
+            .cloned()
+            .collect();
+        let focused = workspaces.iter().find(|ws| ws.focused)?;
+        let mut cmds = Vec::new();
+        for output in reconnected {
+            for num in self.hist.attach(&output) {
+                if let Some(ws) = workspaces
+                    .iter()
+                    .find(|ws| ws.num == num && ws.output != output)
+                {
+                    cmds.push(format!(
+                        "workspace --no-auto-back-and-forth {}; move workspace to output {}",
+                        quote(&ws.name),
+                        quote(&output)
+                    ));
+                }
+            }
+        }
+        if cmds.is_empty() {
+            return None;
+        }
+        cmds.push(format!(
+            "workspace --no-auto-back-and-forth {}",
+            quote(&focused.name)
+        ));
+        Some(cmds.join("; "))
     }
 
-    /// Go to the next/previous workspace and remove the current one from the stack
-    /// Returns the workspace number of the new workspace if it changed
-    async fn rem_ws(&mut self, dir: WSDirection, i3: &mut I3) -> Option<i32> {
+    /// Go to the next/previous entry and remove the current one from the stack, skipping
+    /// scratchpad entries if `ws_only` is set
+    /// Returns the new entry if it changed
+    async fn rem_ws(&mut self, dir: WSDirection, ws_only: bool, i3: &mut I3) -> Option<HistEntry> {
         self.check_timeout();
         let cur_ptr = {
             let hist = self.hist.get(&self.cur_output)?;
             hist.hist_ptr
         };
-        if let Some(new_ws) = self.get_ws(cur_ptr, dir, i3).await {
+        if let Some(new_ws) = self.get_ws(cur_ptr, dir, ws_only, i3).await {
+            self.hist.journal(&self.cur_output);
             let hist = self.hist.get_mut(&self.cur_output)?;
             hist.hist.remove(cur_ptr);
             hist.hist_ptr = new_ws;
@@ -403,24 +1551,132 @@ impl WSHistory {
         }
     }
 
-    /// Check if workspace hasn't been changed since `activity_timer`,
+    /// Leave workspace `ws_num` after it has become empty, going to the previous workspace in
+    /// the stack (or the next one if there is no previous one) and removing it from the history.
+    /// Returns the new entry if it changed
+    async fn leave_empty(&mut self, ws_num: i32, i3: &mut I3) -> Option<HistEntry> {
+        self.commit_peek();
+        let hist = self.hist.get(&self.cur_output)?;
+        if hist.len() == 0 || hist[hist.hist_ptr] != HistEntry::Workspace(ws_num) {
+            return None;
+        }
+        let new_ws = match self.rem_ws(WSDirection::PREV, false, i3).await {
+            Some(new_ws) => new_ws,
+            None => self.rem_ws(WSDirection::NEXT, false, i3).await?,
+        };
+        self.hist.remove_ws(ws_num);
+        Some(new_ws)
+    }
+
+    /// Follow scratchpad windows being shown, hidden and closed. Showing one puts an entry for
+    /// it on top of the stack instead of recording a workspace visit, and leaving it (by hiding
+    /// it or focusing another window) records a return to the focused workspace.
+    async fn track_scratchpad_window(&mut self, w: &WindowData, i3: &mut I3) {
+        let id = w.container.id;
+        if w.change == WindowChange::Close {
+            self.hist.remove_entry(HistEntry::Scratchpad(id));
+            return;
+        }
+        let left = match w.change {
+            WindowChange::Focus => true,
+            WindowChange::Move => self.scratchpad_focused == Some(id),
+            _ => false,
+        };
+        if !left {
+            return;
+        }
+        let tree = match i3.get_tree().await {
+            Ok(tree) => tree,
+            Err(_) => return,
+        };
+        // Changes caused by our own commands aren't recorded
+        let own = self.own_cmds.is_active();
+        let shown = scratchpad_shown(&tree, id);
+        if w.change == WindowChange::Focus && shown == Some(true) {
+            self.scratchpad_focused = Some(id);
+            if !own {
+                self.check_timeout();
+                self.abort_peek();
+                let output = self.cur_output.clone();
+                self.add_entry(HistEntry::Scratchpad(id), &output);
+            }
+        } else if w.change == WindowChange::Focus || shown == Some(false) {
+            if self.scratchpad_focused.take().is_none() || own {
+                return;
+            }
+            let focused = match i3.get_workspaces().await {
+                Ok(workspaces) => workspaces.into_iter().find(|ws| ws.focused),
+                Err(_) => None,
+            };
+            if let Some(ws) = focused {
+                if !self.exclude.get_excluded(i3).await.contains(&ws.num) {
+                    self.add_entry(HistEntry::Workspace(ws.num), &ws.output);
+                }
+            }
+        }
+    }
+
+    /// Number of the focused workspace if it has no windows
+    async fn focused_empty_ws(i3: &mut I3) -> Option<i32> {
+        let num = i3
+            .get_workspaces()
+            .await
+            .ok()?
+            .into_iter()
+            .find(|ws| ws.focused)?
+            .num;
+        let tree = i3.get_tree().await.ok()?;
+        get_workspaces(&tree)
+            .into_iter()
+            .find(|ws| ws.num == Some(num))
+            .filter(|ws| ws.nodes.is_empty() && ws.floating_nodes.is_empty())
+            .map(|_| num)
+    }
+
+    /// Key of `activity_timers` for activity on the current output
+    fn timer_key(&self) -> String {
+        if self.timeout_per_output {
+            self.cur_output.clone()
+        } else {
+            "".into()
+        }
+    }
+
+    /// Reset the pointers of the stacks that timer `key` applies to
+    fn reset_timer_key(&mut self, key: &String) {
+        let outputs = if key.is_empty() && self.hist.is_per_output() {
+            self.hist.outputs()
+        } else {
+            vec![key.clone()]
+        };
+        let moved: Vec<String> = outputs
+            .into_iter()
+            .filter(|o| matches!(self.hist.get(o), Some(hist) if hist.hist_ptr != 0))
+            .collect();
+        for output in moved.iter() {
+            self.hist.journal(output);
+        }
+        if key.is_empty() {
+            self.hist.reset_all();
+        } else {
+            self.hist.reset(key);
+        }
+    }
+
+    /// Check if the timer for the current output has expired without being handled yet,
     /// and reset the pointer if so
-    /// Also resets the timer (all checks are triggered by user activity)
+    /// Also restarts the timer (all checks are triggered by user activity)
     /// Returns true if pointer was reset
     fn check_timeout(&mut self) -> bool {
-        if let Some(timeout) = &self.activity_timeout {
-            let triggered = Instant::now() > self.activity_timer;
-            self.activity_timer = Instant::now() + *timeout;
+        if let Some(timeout) = self.activity_timeout {
+            let key = self.timer_key();
+            // The stack isn't reordered in the middle of a peek traversal
+            let triggered = matches!(self.activity_timers.get(&key), Some(&t) if Instant::now() > t)
+                && self.peek.is_none();
             if triggered {
-                match &mut self.hist.hist {
-                    HistType::Single(hist) => hist.reset_ptr(),
-                    HistType::PerOutput(hist) => {
-                        for (_, h) in hist.iter_mut() {
-                            h.reset_ptr();
-                        }
-                    }
-                }
+                self.reset_timer_key(&key);
             }
+            self.activity_timers.insert(key, Instant::now() + timeout);
             triggered
         } else {
             false
@@ -435,8 +1691,9 @@ impl WSHistory {
             Some(hist) => hist.hist_ptr,
             None => return,
         };
-        if let Some(next_ws) = self.get_ws(hist_ptr, dir, i3).await {
-            if let Some(next_ws2) = self.get_ws(next_ws, dir, i3).await {
+        if let Some(next_ws) = self.get_ws(hist_ptr, dir, false, i3).await {
+            if let Some(next_ws2) = self.get_ws(next_ws, dir, false, i3).await {
+                self.hist.journal(&self.cur_output);
                 self.hist
                     .get_mut(&self.cur_output)
                     .unwrap()
@@ -447,39 +1704,80 @@ impl WSHistory {
     }
 
     /// Print out the workspace history stack for the current output with an arrow pointing to the
-    /// focused one.
+    /// focused one. Each entry shows the workspace name and the title and class of its focused
+    /// window (or of the window itself for scratchpad entries), and visible and urgent workspaces
+    /// are marked.
     async fn display(&self, i3: &mut I3) -> Result<String, ()> {
+        let entries = self.get_entries(i3).await.ok_or(())?;
+        let workspaces = i3.get_workspaces().await.map_err(|_| ())?;
+        let tree = i3.get_tree().await.map_err(|_| ())?;
         let hist = self.hist.get(&self.cur_output).ok_or(())?;
-        let mut out = String::with_capacity(6 * self.hist.hist_sz);
-        let mut filter_out = Vec::with_capacity(0);
-        let per_output = match self.hist.hist {
-            HistType::PerOutput(_) => true,
-            _ => false,
+        let mut out = String::with_capacity(32 * self.hist.hist_sz);
+        let scores = match self.hist.order {
+            HistOrder::Frecency => Some(self.hist.scores()),
+            HistOrder::Recency | HistOrder::MoveToFront => None,
         };
-        if let Ok(workspaces) = i3.get_workspaces().await {
-            filter_out.reserve(workspaces.len());
-            for ws in workspaces {
-                if (per_output && ws.output != self.cur_output)
-                    || (!per_output && self.skip_visible && ws.visible)
-                {
-                    filter_out.push(ws.num);
+        for id in entries {
+            let entry = hist[id];
+            let ws = workspaces.iter().find(|ws| Some(ws.num) == entry.ws_num());
+            let (mut label, window) = describe_entry(entry, &tree);
+            if let (Some(scores), Some(_)) = (&scores, ws) {
+                label.push_str(format!(" ({:.1})", scores.get(&entry).unwrap_or(&0.0)).as_str());
+            }
+            if let Some(window) = window {
+                let class = window
+                    .window_properties
+                    .as_ref()
+                    .and_then(|props| props.class.as_ref());
+                match (&window.name, class) {
+                    (Some(title), Some(class)) => {
+                        label.push_str(format!(" - {} ({})", title, class).as_str())
+                    }
+                    (Some(title), None) => label.push_str(format!(" - {}", title).as_str()),
+                    (None, Some(class)) => label.push_str(format!(" - {}", class).as_str()),
+                    (None, None) => {}
                 }
             }
-        }
-        let mut last = None;
-        for (id, ws) in hist.hist.iter().enumerate() {
-            if filter_out.contains(ws) || matches!(last, Some(last_ws) if last_ws == ws) {
-                continue;
+            if matches!(ws, Some(ws) if ws.urgent) {
+                label.push_str(" [urgent]");
             }
             if id == hist.hist_ptr {
-                out.push_str(format!("{}\t<-\n", ws).as_str());
+                out.push_str(format!("{}\t<-\n", label).as_str());
+            } else if matches!(ws, Some(ws) if ws.visible) {
+                out.push_str(format!("{} [visible]\n", label).as_str());
             } else {
-                out.push_str(format!("{}\n", ws).as_str());
+                out.push_str(format!("{}\n", label).as_str());
+            }
+        }
+        let bookmarks = self.hist.bookmarks(&self.cur_output);
+        if !bookmarks.is_empty() {
+            out.push_str("\nBookmarks\n");
+            for (slot, ws) in bookmarks {
+                out.push_str(format!("{}: {}\n", slot, ws).as_str());
             }
-            last = Some(ws);
         }
         Ok(out)
     }
+
+    /// Show the stack as a notification, replacing the previous one if it is still open.
+    /// The notification closes after `timeout` if given, otherwise after the server's default.
+    async fn show_stack(&mut self, timeout: Option<Duration>, i3: &mut I3) {
+        let hist_msg = match self.display(i3).await {
+            Ok(hist_msg) => hist_msg,
+            Err(_) => return,
+        };
+        let header = match self.hist.hist {
+            HistType::PerOutput(_) => format!("i3 Workspace History ({})", self.cur_output),
+            HistType::Single(_) => "i3 Workspace History".into(),
+        };
+        let id = self
+            .notifier
+            .notify(&header, &hist_msg, timeout, self.notification_id)
+            .await;
+        if id.is_some() {
+            self.notification_id = id;
+        }
+    }
 }
 
 #[async_trait]
@@ -487,71 +1785,233 @@ impl OnEvent for WSHistory {
     fn add_subscriptions(&self, subs: &mut HashSet<u32>) {
         subs.insert(Subscribe::Workspace as u32);
         subs.insert(Subscribe::Binding as u32);
+        self.own_cmds.add_subscriptions(subs);
+        if self.back_on_empty || self.track_scratchpad {
+            subs.insert(Subscribe::Window as u32);
+        }
+        if self.hist.is_per_output() {
+            subs.insert(Subscribe::Output as u32);
+        }
+    }
+
+    async fn on_connect(&mut self, i3: &mut I3) -> Option<String> {
+        self.own_cmds.clear();
+        self.pick = None;
+        self.ws_nums = i3
+            .get_workspaces()
+            .await
+            .ok()?
+            .into_iter()
+            .map(|ws| (ws.id, ws.num))
+            .collect();
+        None
     }
 
     async fn handle_event(&mut self, e: &Event, i3: &mut I3) -> Option<String> {
+        if let Event::Binding(key) = e {
+            if self.handle_bookmark(key, i3).await {
+                self.publish(i3).await;
+                return None;
+            }
+        }
+        if let Some(cmd) = self.handle(e, i3).await {
+            self.own_cmds.run(&cmd, i3).await;
+            if let (Event::Binding(key), Some(timeout)) = (e, self.show_stack_on_traverse) {
+                let traversal = [
+                    &self.binding_prev,
+                    &self.binding_next,
+                    &self.binding_move_prev,
+                    &self.binding_move_next,
+                    &self.binding_peek_prev,
+                    &self.binding_peek_next,
+                ];
+                if traversal.iter().any(|b| matches!(b, Some(kb) if kb == key)) {
+                    self.show_stack(Some(timeout), i3).await;
+                }
+            }
+        }
+        self.publish(i3).await;
+        None
+    }
+
+    fn next_timeout(&self) -> Option<Instant> {
+        self.activity_timers
+            .values()
+            .copied()
+            .chain(self.own_cmds.expiry())
+            .min()
+    }
+
+    async fn on_timeout(&mut self, i3: &mut I3) -> Option<String> {
+        self.own_cmds.expire();
+        let now = Instant::now();
+        let expired: Vec<String> = self
+            .activity_timers
+            .iter()
+            .filter(|(_, &t)| t <= now)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in expired {
+            self.activity_timers.remove(&key);
+            // The stack isn't reordered in the middle of a peek traversal
+            if self.peek.is_none() {
+                self.reset_timer_key(&key);
+            }
+        }
+        self.publish(i3).await;
+        None
+    }
+
+    fn set_background(&mut self, tx: BackgroundSender) {
+        self.background = Some(tx);
+    }
+
+    async fn on_background(&mut self, msg: &str, i3: &mut I3) -> Option<String> {
+        if let Some(cmd) = self.picked(msg, i3).await {
+            self.own_cmds.run(&cmd, i3).await;
+        }
+        self.publish(i3).await;
+        None
+    }
+}
+
+impl WSHistory {
+    /// Handle an event, returning the i3 command to run (if any)
+    async fn handle(&mut self, e: &Event, i3: &mut I3) -> Option<String> {
         match e {
             Event::Workspace(ws) => {
                 self.check_timeout();
-                if let Some(current) = &ws.current {
-                    if let Some(output) = &current.output {
-                        self.cur_output = output.clone();
+                let mut cmd = None;
+                if ws.change == WorkspaceChange::Empty && self.back_on_empty {
+                    if let Some(num) = ws
+                        .current
+                        .as_ref()
+                        .filter(|c| c.focused)
+                        .and_then(|c| c.num)
+                    {
+                        cmd = self
+                            .leave_empty(num, i3)
+                            .await
+                            .map(|new_ws| self.focus_cmd(new_ws));
                     }
                 }
-                if ws.change != WorkspaceChange::Init {
-                    if self.ignore_ctr > 0 {
-                        self.ignore_ctr -= 1;
-                    } else if let (Some(old), Some(current)) = (&ws.old, &ws.current) {
-                        if old.num != current.num {
-                            if let (Some(old_num), Some(output)) = (old.num, &old.output) {
-                                self.add_ws(old_num, output);
+                match ws.change {
+                    WorkspaceChange::Init => {}
+                    WorkspaceChange::Move => {
+                        if let Some(current) = &ws.current {
+                            if let (Some(num), Some(output), false) =
+                                (current.num, &current.output, self.exclude.matches(current))
+                            {
+                                self.move_ws(num, output, i3).await;
+                            }
+                        }
+                    }
+                    WorkspaceChange::Rename => {
+                        if let Some(current) = &ws.current {
+                            if let (Some(&old_num), Some(new_num)) =
+                                (self.ws_nums.get(&current.id), current.num)
+                            {
+                                if old_num != new_num {
+                                    self.hist.rename_ws(old_num, new_num);
+                                }
                             }
-                            if let (Some(cur_num), Some(output)) = (current.num, &current.output) {
-                                self.add_ws(cur_num, output);
+                        }
+                    }
+                    _ => {
+                        if ws.change == WorkspaceChange::Empty && self.purge_empty {
+                            if let Some(num) = ws.current.as_ref().and_then(|c| c.num) {
+                                self.hist.remove_ws(num);
+                            }
+                        } else if let Some(output) =
+                            ws.current.as_ref().and_then(|c| c.output.as_ref())
+                        {
+                            self.cur_output = output.clone();
+                        }
+                        if ws.change == WorkspaceChange::Focus {
+                            if let Some(num) = ws.current.as_ref().and_then(|c| c.num) {
+                                self.hist.visit(num);
+                            }
+                        }
+                        // Changes caused by our own commands aren't recorded
+                        let own = self.own_cmds.is_active();
+                        if let (false, Some(old), Some(current)) = (own, &ws.old, &ws.current) {
+                            if old.num != current.num {
+                                // Workspace was changed by something else during a peek
+                                self.abort_peek();
+                                for node in [old, current] {
+                                    if let (Some(num), Some(output), false) =
+                                        (node.num, &node.output, self.exclude.matches(node))
+                                    {
+                                        self.add_entry(HistEntry::Workspace(num), output);
+                                    }
+                                }
                             }
                         }
                     }
                 }
-                None
+                for node in ws.current.iter().chain(ws.old.iter()) {
+                    if ws.change == WorkspaceChange::Empty {
+                        self.ws_nums.remove(&node.id);
+                    } else if let Some(num) = node.num {
+                        self.ws_nums.insert(node.id, num);
+                    }
+                }
+                cmd
+            }
+            Event::Window(w) => {
+                if self.track_scratchpad {
+                    self.track_scratchpad_window(w, i3).await;
+                }
+                if !(self.back_on_empty && w.change == WindowChange::Close && w.container.focused) {
+                    return None;
+                }
+                // Closing the last window leaves i3 on the empty workspace
+                let num = Self::focused_empty_ws(i3).await?;
+                self.check_timeout();
+                self.leave_empty(num, i3)
+                    .await
+                    .map(|new_ws| self.focus_cmd(new_ws))
             }
             Event::Binding(key) => {
+                let is_peek = [&self.binding_peek_prev, &self.binding_peek_next]
+                    .iter()
+                    .any(|b| matches!(b, Some(kb) if kb == key));
+                if matches!(&self.binding_peek_commit, Some(kb) if kb == key) {
+                    self.commit_peek();
+                    return None;
+                } else if !is_peek {
+                    // Any other binding also ends the traversal
+                    self.commit_peek();
+                }
                 if self.hist.get(&self.cur_output).is_some()
                     && self.hist.get(&self.cur_output).unwrap().len() > 0
                 {
-                    if matches!(&self.binding_prev, Some(kb) if kb == key) {
-                        self.goto_ws(WSDirection::PREV, i3)
+                    if matches!(&self.binding_peek_prev, Some(kb) if kb == key) {
+                        self.peek_ws(WSDirection::PREV, i3)
+                            .await
+                            .map(|new_ws| self.focus_cmd(new_ws))
+                    } else if matches!(&self.binding_peek_next, Some(kb) if kb == key) {
+                        self.peek_ws(WSDirection::NEXT, i3)
                             .await
-                            .and_then(|new_ws| {
-                                self.ignore_ctr += 1;
-                                Some(format!("workspace number {}", new_ws))
-                            })
+                            .map(|new_ws| self.focus_cmd(new_ws))
+                    } else if matches!(&self.binding_prev, Some(kb) if kb == key) {
+                        self.goto_ws(WSDirection::PREV, false, i3)
+                            .await
+                            .map(|new_ws| self.focus_cmd(new_ws))
                     } else if matches!(&self.binding_move_prev, Some(kb) if kb == key) {
-                        self.goto_ws(WSDirection::PREV, i3)
+                        self.goto_ws(WSDirection::PREV, true, i3)
                             .await
-                            .and_then(|new_ws| {
-                                self.ignore_ctr += 2;
-                                Some(format!(
-                                    "move container to workspace number {0}; workspace number {0}",
-                                    new_ws
-                                ))
-                            })
+                            .and_then(HistEntry::ws_num)
+                            .map(|new_ws| self.move_cmd(new_ws))
                     } else if matches!(&self.binding_next, Some(kb) if kb == key) {
-                        self.goto_ws(WSDirection::NEXT, i3)
+                        self.goto_ws(WSDirection::NEXT, false, i3)
                             .await
-                            .and_then(|new_ws| {
-                                self.ignore_ctr += 1;
-                                Some(format!("workspace number {}", new_ws))
-                            })
+                            .map(|new_ws| self.focus_cmd(new_ws))
                     } else if matches!(&self.binding_move_next, Some(kb) if kb == key) {
-                        self.goto_ws(WSDirection::NEXT, i3)
+                        self.goto_ws(WSDirection::NEXT, true, i3)
                             .await
-                            .and_then(|new_ws| {
-                                self.ignore_ctr += 2;
-                                Some(format!(
-                                    "move container to workspace number {0}; workspace number {0}",
-                                    new_ws
-                                ))
-                            })
+                            .and_then(HistEntry::ws_num)
+                            .map(|new_ws| self.move_cmd(new_ws))
                     } else if matches!(&self.binding_swap_prev, Some(kb) if kb == key) {
                         self.swap_ws(WSDirection::PREV, i3).await;
                         None
@@ -562,55 +2022,73 @@ impl OnEvent for WSHistory {
                         // check timeout resets all history anyway, so no need to re-do if it's
                         // just been done
                         if !self.check_timeout() {
-                            if let Some(hist) = self.hist.get_mut(&self.cur_output) {
-                                hist.reset_ptr();
-                            }
+                            self.hist.journal(&self.cur_output);
+                            self.hist.reset(&self.cur_output);
                         }
                         None
+                    } else if matches!(&self.binding_undo, Some(kb) if kb == key) {
+                        self.check_timeout();
+                        self.hist
+                            .undo(&self.cur_output, true)
+                            .map(|new_ws| self.focus_cmd(new_ws))
+                    } else if matches!(&self.binding_redo, Some(kb) if kb == key) {
+                        self.check_timeout();
+                        self.hist
+                            .undo(&self.cur_output, false)
+                            .map(|new_ws| self.focus_cmd(new_ws))
                     } else if matches!(&self.binding_to_head, Some(kb) if kb == key) {
-                        self.goto_head(i3).await.and_then(|new_ws| {
-                            self.ignore_ctr += 1;
-                            Some(format!("workspace number {}", new_ws))
-                        })
+                        self.goto_head(false, i3)
+                            .await
+                            .map(|new_ws| self.focus_cmd(new_ws))
                     } else if matches!(&self.binding_move_to_head, Some(kb) if kb == key) {
-                        self.goto_head(i3).await.and_then(|new_ws| {
-                            self.ignore_ctr += 2;
-                            Some(format!(
-                                "move container to workspace number {0}; workspace number {0}",
-                                new_ws
-                            ))
-                        })
+                        self.goto_head(true, i3)
+                            .await
+                            .and_then(HistEntry::ws_num)
+                            .map(|new_ws| self.move_cmd(new_ws))
+                    } else if matches!(&self.binding_send_prev, Some(kb) if kb == key) {
+                        self.get_target(Some(WSDirection::PREV), i3)
+                            .await
+                            .map(|ws| format!("move container to workspace number {}", ws))
+                    } else if matches!(&self.binding_send_next, Some(kb) if kb == key) {
+                        self.get_target(Some(WSDirection::NEXT), i3)
+                            .await
+                            .map(|ws| format!("move container to workspace number {}", ws))
+                    } else if matches!(&self.binding_send_to_head, Some(kb) if kb == key) {
+                        self.get_target(None, i3)
+                            .await
+                            .map(|ws| format!("move container to workspace number {}", ws))
+                    } else if matches!(&self.binding_move_all_prev, Some(kb) if kb == key) {
+                        self.move_all(Some(WSDirection::PREV), i3).await
+                    } else if matches!(&self.binding_move_all_next, Some(kb) if kb == key) {
+                        self.move_all(Some(WSDirection::NEXT), i3).await
+                    } else if matches!(&self.binding_move_all_to_head, Some(kb) if kb == key) {
+                        self.move_all(None, i3).await
                     } else if matches!(&self.binding_rem_and_prev, Some(kb) if kb == key) {
-                        self.rem_ws(WSDirection::PREV, i3).await.and_then(|new_ws| {
-                            self.ignore_ctr += 1;
-                            Some(format!("workspace number {}", new_ws))
-                        })
+                        self.rem_ws(WSDirection::PREV, false, i3)
+                            .await
+                            .map(|new_ws| self.focus_cmd(new_ws))
                     } else if matches!(&self.binding_rem_and_next, Some(kb) if kb == key) {
-                        self.rem_ws(WSDirection::NEXT, i3).await.and_then(|new_ws| {
-                            self.ignore_ctr += 1;
-                            Some(format!("workspace number {}", new_ws))
-                        })
+                        self.rem_ws(WSDirection::NEXT, false, i3)
+                            .await
+                            .map(|new_ws| self.focus_cmd(new_ws))
+                    } else if let Some(n) = self.bindings_goto.iter().position(|kb| kb == key) {
+                        self.goto_nth(n + 1, false, i3)
+                            .await
+                            .map(|new_ws| self.focus_cmd(new_ws))
+                    } else if let Some(n) = self.bindings_move_to.iter().position(|kb| kb == key) {
+                        self.goto_nth(n + 1, true, i3)
+                            .await
+                            .and_then(HistEntry::ws_num)
+                            .map(|new_ws| self.move_cmd(new_ws))
+                    } else if matches!(&self.binding_picker, Some(kb) if kb == key) {
+                        self.pick(false, i3).await;
+                        None
+                    } else if matches!(&self.binding_picker_move, Some(kb) if kb == key) {
+                        self.pick(true, i3).await;
+                        None
                     } else if matches!(&self.binding_show_stack, Some(kb) if kb == key) {
                         self.check_timeout();
-                        if let Ok(hist_msg) = self.display(i3).await {
-                            let header = match self.hist.hist {
-                                HistType::PerOutput(_) => {
-                                    format!("i3 Workspace History ({})", self.cur_output)
-                                }
-                                HistType::Single(_) => "i3 Workspace History".into(),
-                            };
-                            match notify_rust::Notification::new()
-                                .summary(header.as_str())
-                                .body(hist_msg.as_str())
-                                .appname("i3-companion")
-                                .show()
-                            {
-                                Err(err) => {
-                                    eprintln!("Error showing history stack: {}", err);
-                                }
-                                Ok(_) => (),
-                            }
-                        }
+                        self.show_stack(None, i3).await;
                         None
                     } else {
                         None
@@ -619,11 +2097,70 @@ impl OnEvent for WSHistory {
                     None
                 }
             }
+            Event::Output(_) => self.update_outputs(i3).await,
+            Event::Tick(tick) => {
+                self.own_cmds.handle_tick(&tick.payload);
+                None
+            }
             _ => None,
         }
     }
 }
 
+/// Name of `entry` and its focused window (the window itself for a scratchpad entry)
+fn describe_entry(entry: HistEntry, tree: &Node) -> (String, Option<&Node>) {
+    match entry {
+        HistEntry::Workspace(num) => {
+            let ws = get_workspaces(tree)
+                .into_iter()
+                .find(|ws| ws.num == Some(num));
+            let name = ws
+                .and_then(|ws| ws.name.clone())
+                .unwrap_or_else(|| num.to_string());
+            (name, ws.and_then(get_focused_window))
+        }
+        HistEntry::Scratchpad(id) => (
+            entry.to_string(),
+            get_windows(tree).into_iter().find(|w| w.id == id),
+        ),
+    }
+}
+
+/// Run the menu command `cmd` with `input` on stdin, returning the line that was selected.
+/// The picker is killed if nothing is selected within `PICKER_TIMEOUT`.
+async fn run_picker(cmd: &[String], input: &str) -> Option<String> {
+    let mut child = Command::new(&cmd[0])
+        .args(&cmd[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| {
+            eprintln!(
+                "WARNING: error executing picker `{}` -> {}",
+                cmd.join(" "),
+                e
+            )
+        })
+        .ok()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        if let Err(e) = stdin.write_all(input.as_bytes()).await {
+            eprintln!("WARNING: error writing to picker -> {}", e);
+        }
+    }
+    let output = tokio::time::timeout(PICKER_TIMEOUT, child.wait_with_output())
+        .await
+        .map_err(|_| eprintln!("WARNING: picker `{}` timed out", cmd.join(" ")))
+        .ok()?
+        .ok()?;
+    String::from_utf8(output.stdout)
+        .ok()?
+        .lines()
+        .next()
+        .map(|line| line.to_string())
+}
+
+#[allow(clippy::upper_case_acronyms)]
 #[derive(PartialEq, Eq, Clone, Copy)]
 pub enum WSDirection {
     /// Newer workspaces (towards the top of the stack, `hist_ptr -= x`)